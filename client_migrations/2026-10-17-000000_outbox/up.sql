@@ -0,0 +1,10 @@
+-- Logs waiting to be shipped to the server, in the order they were saved
+CREATE TABLE outbox (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    -- sent along with the log so the server can discard copies it has already stored
+    ingest_id TEXT NOT NULL,
+    -- JSON
+    source TEXT NOT NULL,
+    -- SimpleLog as JSON
+    log TEXT NOT NULL
+);
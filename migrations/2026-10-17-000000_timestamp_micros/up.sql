@@ -0,0 +1,34 @@
+CREATE TABLE log_new (
+    id INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    -- microseconds since the unix epoch, UTC
+    timestamp BIGINT NOT NULL,
+    level TEXT NOT NULL,
+    location TEXT NOT NULL,
+    content TEXT NOT NULL,
+    PRIMARY KEY(id)
+);
+
+-- timestamps were stored as JSON encoded RFC3339 strings, strftime only gives whole seconds
+-- so the fraction (between the seconds and the "Z"/"+HH:MM" offset) is converted separately
+INSERT INTO log_new (id, source, timestamp, level, location, content)
+SELECT
+    id,
+    source,
+    CAST(strftime('%s', ts) AS INTEGER) * 1000000 + CASE
+        WHEN substr(ts, 20, 1) = '.' THEN CAST(substr(
+            substr(ts, 21, length(ts) - 20 - CASE WHEN upper(substr(ts, -1)) = 'Z' THEN 1 ELSE 6 END) || '000000',
+            1,
+            6
+        ) AS INTEGER)
+        ELSE 0
+    END,
+    level,
+    location,
+    content
+FROM (SELECT *, json_extract(timestamp, '$') AS ts FROM log);
+
+DROP TABLE log;
+ALTER TABLE log_new RENAME TO log;
+
+CREATE INDEX log_timestamp ON log (timestamp);
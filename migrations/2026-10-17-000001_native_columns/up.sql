@@ -0,0 +1,34 @@
+CREATE TABLE log_new (
+    id INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    -- microseconds since the unix epoch, UTC
+    timestamp BIGINT NOT NULL,
+    -- Trace = 0, Debug = 1, Info = 2, Warn = 3, Error = 4
+    level INTEGER NOT NULL,
+    location TEXT NOT NULL,
+    content TEXT NOT NULL,
+    PRIMARY KEY(id)
+);
+
+-- level, location and content were stored JSON encoded
+INSERT INTO log_new (id, source, timestamp, level, location, content)
+SELECT
+    id,
+    source,
+    timestamp,
+    CASE json_extract(level, '$')
+        WHEN 'Trace' THEN 0
+        WHEN 'Debug' THEN 1
+        WHEN 'Info' THEN 2
+        WHEN 'Warn' THEN 3
+        WHEN 'Error' THEN 4
+    END,
+    json_extract(location, '$'),
+    json_extract(content, '$')
+FROM log;
+
+DROP TABLE log;
+ALTER TABLE log_new RENAME TO log;
+
+CREATE INDEX log_timestamp ON log (timestamp);
+CREATE INDEX log_level ON log (level);
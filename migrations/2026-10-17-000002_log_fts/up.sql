@@ -0,0 +1,17 @@
+-- external content table, the text itself stays in log and only the index lives here
+CREATE VIRTUAL TABLE log_fts USING fts5(content, content='log', content_rowid='id');
+
+CREATE TRIGGER log_fts_insert AFTER INSERT ON log BEGIN
+    INSERT INTO log_fts (rowid, content) VALUES (new.id, new.content);
+END;
+
+CREATE TRIGGER log_fts_delete AFTER DELETE ON log BEGIN
+    INSERT INTO log_fts (log_fts, rowid, content) VALUES ('delete', old.id, old.content);
+END;
+
+CREATE TRIGGER log_fts_update AFTER UPDATE OF content ON log BEGIN
+    INSERT INTO log_fts (log_fts, rowid, content) VALUES ('delete', old.id, old.content);
+    INSERT INTO log_fts (rowid, content) VALUES (new.id, new.content);
+END;
+
+INSERT INTO log_fts (log_fts) VALUES ('rebuild');
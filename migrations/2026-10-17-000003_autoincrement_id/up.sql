@@ -0,0 +1,35 @@
+-- ids are assigned by SQLite from here on, AUTOINCREMENT keeps them from being reused
+-- after the newest logs are deleted so cursors stay valid
+CREATE TABLE log_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    source TEXT NOT NULL,
+    -- microseconds since the unix epoch, UTC
+    timestamp BIGINT NOT NULL,
+    -- Trace = 0, Debug = 1, Info = 2, Warn = 3, Error = 4
+    level INTEGER NOT NULL,
+    location TEXT NOT NULL,
+    content TEXT NOT NULL
+);
+
+INSERT INTO log_new (id, source, timestamp, level, location, content)
+SELECT id, source, timestamp, level, location, content FROM log;
+
+-- ids are unchanged so log_fts stays valid, only the triggers have to be recreated
+DROP TABLE log;
+ALTER TABLE log_new RENAME TO log;
+
+CREATE INDEX log_timestamp ON log (timestamp);
+CREATE INDEX log_level ON log (level);
+
+CREATE TRIGGER log_fts_insert AFTER INSERT ON log BEGIN
+    INSERT INTO log_fts (rowid, content) VALUES (new.id, new.content);
+END;
+
+CREATE TRIGGER log_fts_delete AFTER DELETE ON log BEGIN
+    INSERT INTO log_fts (log_fts, rowid, content) VALUES ('delete', old.id, old.content);
+END;
+
+CREATE TRIGGER log_fts_update AFTER UPDATE OF content ON log BEGIN
+    INSERT INTO log_fts (log_fts, rowid, content) VALUES ('delete', old.id, old.content);
+    INSERT INTO log_fts (rowid, content) VALUES (new.id, new.content);
+END;
@@ -0,0 +1,4 @@
+-- Key chosen by the sender of a log, a log which is sent again with the same key is only stored once
+ALTER TABLE log ADD COLUMN ingest_id TEXT;
+
+CREATE UNIQUE INDEX log_ingest_id ON log (ingest_id) WHERE ingest_id IS NOT NULL;
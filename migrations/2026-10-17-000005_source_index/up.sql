@@ -0,0 +1,2 @@
+-- Covers grouping by source with the first and last timestamp, also serves source filters
+CREATE INDEX log_source ON log (source, timestamp);
@@ -0,0 +1,2 @@
+-- Structured key-value fields of the log as a JSON object, NULL when it has none
+ALTER TABLE log ADD COLUMN fields TEXT;
@@ -0,0 +1,7 @@
+-- Span the log was raised in, trace_id is shared by every log of one request across sources
+ALTER TABLE log ADD COLUMN trace_id TEXT;
+ALTER TABLE log ADD COLUMN span_id TEXT;
+ALTER TABLE log ADD COLUMN parent_span_id TEXT;
+ALTER TABLE log ADD COLUMN span_name TEXT;
+
+CREATE INDEX log_trace ON log (trace_id, timestamp) WHERE trace_id IS NOT NULL;
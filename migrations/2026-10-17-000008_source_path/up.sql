@@ -0,0 +1,9 @@
+-- Components of the source from most to least general, each followed by \x1F, see source::Source
+-- and Builder::source_path.
+-- Filled in from Rust at startup for existing logs.
+ALTER TABLE log ADD COLUMN source_path TEXT;
+
+CREATE INDEX log_source_path ON log (source_path, timestamp);
+
+-- Keeps finding logs which still need a path cheap once they all have one
+CREATE INDEX log_source_path_missing ON log (source) WHERE source_path IS NULL;
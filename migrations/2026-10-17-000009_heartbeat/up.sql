@@ -0,0 +1,7 @@
+-- Marks the logs written by the heartbeat monitor, which were only told apart by their location before
+ALTER TABLE log ADD COLUMN heartbeat BOOLEAN NOT NULL DEFAULT 0;
+
+UPDATE log SET heartbeat = 1 WHERE location = 'log_manager::heartbeat';
+
+-- Covers finding when each source last logged, leaving out the monitor's own logs
+CREATE INDEX log_heartbeat_source ON log (heartbeat, source, timestamp);
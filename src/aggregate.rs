@@ -0,0 +1,177 @@
+use diesel::{
+    dsl::{self, count_star, sql},
+    sql_types::{BigInt, Integer, Nullable, Text},
+    ExpressionMethods, NullableExpressionMethods, QueryDsl, RunQueryDsl, SqliteConnection,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    error::{DieselResultError, Error, SerdeError},
+    logs::{micros_to_timestamp, Level},
+    schema::log::dsl::{
+        heartbeat as heartbeat_db, id as id_db, log as log_data, source as source_db,
+        timestamp as timestamp_db,
+    },
+    search::SearchFilter,
+};
+
+///Level, source, bucket start in microseconds and count, as loaded by GroupBy::load
+pub(crate) type AggregateColumns = (Option<i32>, Option<String>, Option<i64>, i64);
+
+///Source, first and last timestamp in microseconds and count, as loaded by SourceSummary::load_all
+pub(crate) type SourceRow = (String, i64, i64, i64);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBucket {
+    Minute,
+    Hour,
+    ///UTC days
+    Day,
+}
+
+impl TimeBucket {
+    fn micros(&self) -> i64 {
+        match self {
+            Self::Minute => 60_000_000,
+            Self::Hour => 3_600_000_000,
+            Self::Day => 86_400_000_000,
+        }
+    }
+}
+
+///What LogManager::aggregate counts logs by, fields which aren't grouped by are None in every row.
+///Grouping by nothing returns a single row with the total count, or no rows when nothing matches.
+///Logs written by the heartbeat monitor are counted unless left out with SearchFilter::heartbeat.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupBy {
+    level: bool,
+    source: bool,
+    bucket: Option<TimeBucket>,
+}
+
+impl GroupBy {
+    pub fn level(mut self) -> Self {
+        self.level = true;
+        self
+    }
+
+    pub fn source(mut self) -> Self {
+        self.source = true;
+        self
+    }
+
+    ///Groups logs by the start of the minute, hour or day they fall in
+    pub fn bucket(mut self, bucket: TimeBucket) -> Self {
+        self.bucket = Some(bucket);
+        self
+    }
+
+    ///Level, source, bucket start and count of each group of the logs matching filter,
+    ///ordered by bucket then level then source.
+    ///Columns which aren't grouped by are selected as NULL so every grouping has the same row type.
+    pub(crate) fn load<S: Serialize>(
+        &self,
+        filter: &SearchFilter<S>,
+        connection: &mut SqliteConnection,
+    ) -> Result<Vec<AggregateColumns>, Error> {
+        let query = filter.query()?;
+        let level = match self.level {
+            true => "level".to_string(),
+            false => "NULL".to_string(),
+        };
+        let source = match self.source {
+            true => "source".to_string(),
+            false => "NULL".to_string(),
+        };
+        let bucket = match self.bucket {
+            Some(bucket) => format!("(timestamp / {0}) * {0}", bucket.micros()),
+            None => "NULL".to_string(),
+        };
+        let grouping = format!("{bucket}, {level}, {source}");
+        //Boxed queries can't be grouped, so the filter is applied through a subquery
+        log_data
+            .filter(id_db.eq_any(query.select(id_db)))
+            .group_by(sql::<Nullable<BigInt>>(&grouping))
+            .select((
+                sql::<Nullable<Integer>>(&level),
+                sql::<Nullable<Text>>(&source),
+                sql::<Nullable<BigInt>>(&bucket),
+                count_star(),
+            ))
+            .order(sql::<Nullable<BigInt>>(&grouping))
+            .load(connection)
+            .map_err(|err| filter.query_error(err))
+    }
+}
+
+///Number of logs in one group, see GroupBy
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AggregateRow<S> {
+    pub level: Option<Level>,
+    pub source: Option<S>,
+    ///Start of the time bucket, RFC3339
+    pub bucket: Option<String>,
+    pub count: i64,
+}
+
+impl<S: DeserializeOwned> AggregateRow<S> {
+    pub(crate) fn from_row(
+        (level, source, bucket, count): AggregateColumns,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            level: level.map(Level::try_from).transpose()?,
+            source: match source {
+                Some(source) => {
+                    Some(serde_json::from_str(&source).map_err(|err| {
+                        Error::DeserializingField("source".into(), SerdeError(err))
+                    })?)
+                }
+                None => None,
+            },
+            bucket: bucket.map(micros_to_timestamp).transpose()?,
+            count,
+        })
+    }
+}
+
+///Every log written by one source, see LogManager::sources. Heartbeat logs stored under the source aren't counted.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SourceSummary<S> {
+    pub source: S,
+    ///RFC3339, timestamp of the oldest log still stored
+    pub first_seen: String,
+    ///RFC3339
+    pub last_seen: String,
+    pub count: i64,
+}
+
+impl<S: DeserializeOwned> SourceSummary<S> {
+    ///Every distinct source with its oldest and newest timestamp and log count,
+    ///least recently seen first
+    pub(crate) fn load_all(connection: &mut SqliteConnection) -> Result<Vec<SourceRow>, Error> {
+        log_data
+            .filter(heartbeat_db.eq(false))
+            .group_by(source_db)
+            .select((
+                source_db,
+                dsl::min(timestamp_db).assume_not_null(),
+                dsl::max(timestamp_db).assume_not_null(),
+                count_star(),
+            ))
+            .order(dsl::max(timestamp_db).asc())
+            .load(connection)
+            .map_err(|err| Error::DieselResult(DieselResultError(err)))
+    }
+
+    pub(crate) fn from_row(
+        (source, first_seen, last_seen, count): SourceRow,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            source: serde_json::from_str(&source)
+                .map_err(|err| Error::DeserializingField("source".into(), SerdeError(err)))?,
+            first_seen: micros_to_timestamp(first_seen)?,
+            last_seen: micros_to_timestamp(last_seen)?,
+            count,
+        })
+    }
+}
@@ -0,0 +1,372 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use regex::Regex;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+use crate::{
+    database::model::LogModel,
+    error::Error,
+    logs::{micros_to_timestamp, Level},
+    search::{LogMatcher, SearchFilter},
+};
+
+///A rule firing for a log
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Alert<S> {
+    ///Name of the rule
+    pub rule: String,
+    ///Source of the log which made the rule fire
+    pub source: S,
+    pub level: Level,
+    ///RFC3339
+    pub timestamp: String,
+    pub location: String,
+    pub content: String,
+    ///Matching logs within the threshold window, 1 for rules without a threshold
+    pub count: usize,
+}
+
+///What happens when a rule fires, actions run on a background task so they don't hold up the writer
+pub enum AlertAction<S> {
+    ///Shouldn't block
+    Callback(Box<dyn Fn(Alert<S>) + Send + Sync>),
+    ///Appends the alert to the file as a line of JSON
+    File(PathBuf),
+    ///POSTs the alert as JSON
+    #[cfg(feature = "webhook")]
+    Webhook(String),
+}
+
+impl<S> AlertAction<S> {
+    pub fn callback(callback: impl Fn(Alert<S>) + Send + Sync + 'static) -> Self {
+        Self::Callback(Box::new(callback))
+    }
+}
+
+///Condition evaluated against every log once the manager has written it, logs which are dropped
+///or fail to write never fire. Windows and cool-downs are measured from when logs are written, not their timestamps.
+pub struct AlertRule<S> {
+    name: String,
+    matcher: LogMatcher,
+    regex: Option<Regex>,
+    threshold: Option<(usize, Duration)>,
+    per_source: bool,
+    cooldown: Duration,
+    actions: Vec<AlertAction<S>>,
+}
+
+impl<S: Serialize + DeserializeOwned + 'static> AlertRule<S> {
+    ///Fires for every log matching filter, at most once per cool-down which defaults to 5 minutes.
+    ///Full-text filters can't be evaluated outside of SQLite and return Error::UnsupportedFilter.
+    pub fn new(name: impl Into<String>, filter: &SearchFilter<S>) -> Result<Self, Error> {
+        Ok(Self {
+            name: name.into(),
+            matcher: filter.matcher()?,
+            regex: None,
+            threshold: None,
+            per_source: false,
+            cooldown: Duration::from_secs(300),
+            actions: Vec::new(),
+        })
+    }
+
+    ///Only matches logs whose content matches regex
+    pub fn regex(mut self, regex: Regex) -> Self {
+        self.regex = Some(regex);
+        self
+    }
+
+    ///Only fires once more than count matching logs were written within window
+    pub fn threshold(mut self, count: usize, window: Duration) -> Self {
+        self.threshold = Some((count, window));
+        self
+    }
+
+    ///Counts the threshold and cool-down separately for each source,
+    ///e.g. "more than 10 Error logs from any Agent within 5 minutes"
+    pub fn per_source(mut self) -> Self {
+        self.per_source = true;
+        self
+    }
+
+    ///Minimum time between two alerts of this rule, or of this rule and source with per_source
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub fn action(mut self, action: AlertAction<S>) -> Self {
+        self.actions.push(action);
+        self
+    }
+
+    ///Drops S so the rule can be held by the Builder
+    pub(crate) fn into_rule(self) -> Rule {
+        let actions = self
+            .actions
+            .into_iter()
+            .map(|action| match action {
+                AlertAction::Callback(callback) => {
+                    Action::Callback(Box::new(move |alert| match alert.decode::<S>() {
+                        Ok(alert) => callback(alert),
+                        Err(err) => warn!("Skipping alert callback for rule {}: {err}", alert.rule),
+                    }))
+                }
+                AlertAction::File(path) => Action::File(path),
+                #[cfg(feature = "webhook")]
+                AlertAction::Webhook(url) => Action::Webhook(url),
+            })
+            .collect();
+        Rule {
+            name: self.name,
+            matcher: self.matcher,
+            regex: self.regex,
+            threshold: self.threshold,
+            per_source: self.per_source,
+            cooldown: self.cooldown,
+            actions,
+        }
+    }
+}
+
+type Callback = Box<dyn Fn(&Alert<String>) + Send + Sync>;
+
+///Index of the rule which fired and its alert, with the source as JSON
+pub(crate) type FiredAlert = (usize, Alert<String>);
+
+enum Action {
+    Callback(Callback),
+    File(PathBuf),
+    #[cfg(feature = "webhook")]
+    Webhook(String),
+}
+
+///AlertRule with sources as JSON
+pub(crate) struct Rule {
+    name: String,
+    matcher: LogMatcher,
+    regex: Option<Regex>,
+    threshold: Option<(usize, Duration)>,
+    per_source: bool,
+    cooldown: Duration,
+    actions: Vec<Action>,
+}
+
+impl Alert<String> {
+    fn decode<S: DeserializeOwned>(&self) -> Result<Alert<S>, serde_json::Error> {
+        Ok(Alert {
+            rule: self.rule.to_owned(),
+            source: serde_json::from_str(&self.source)?,
+            level: self.level,
+            timestamp: self.timestamp.to_owned(),
+            location: self.location.to_owned(),
+            content: self.content.to_owned(),
+            count: self.count,
+        })
+    }
+}
+
+///How often the state of rules which can't fire or be held back by it anymore is dropped
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct RuleState {
+    ///When each matching log within the threshold window was written
+    recent: VecDeque<Instant>,
+    last_fired: Option<Instant>,
+}
+
+impl RuleState {
+    ///Every log is outside the threshold window and the cool-down is over, the same as no state at all
+    fn is_expired(&self, rule: &Rule, now: Instant) -> bool {
+        let window = rule.threshold.map(|(_, window)| window).unwrap_or_default();
+        self.recent
+            .back()
+            .is_none_or(|written| now.duration_since(*written) > window)
+            && self
+                .last_fired
+                .is_none_or(|last_fired| now.duration_since(last_fired) >= rule.cooldown)
+    }
+}
+
+///Keyed by rule index and, for per_source rules, the source
+struct EngineState {
+    rules: HashMap<(usize, Option<String>), RuleState>,
+    last_pruned: Instant,
+}
+
+///Evaluates rules as logs are written and hands fired alerts to the task running their actions
+pub(crate) struct AlertEngine {
+    rules: Vec<Rule>,
+    state: Mutex<EngineState>,
+    sender: mpsc::UnboundedSender<FiredAlert>,
+    receiver: Mutex<Option<mpsc::UnboundedReceiver<FiredAlert>>>,
+    #[cfg(feature = "webhook")]
+    http: reqwest::Client,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            rules,
+            state: Mutex::new(EngineState {
+                rules: HashMap::new(),
+                last_pruned: Instant::now(),
+            }),
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+            #[cfg(feature = "webhook")]
+            http: reqwest::Client::new(),
+        }
+    }
+
+    ///Receiving end for the task running the actions, only handed out once
+    pub fn take_receiver(&self) -> Option<mpsc::UnboundedReceiver<FiredAlert>> {
+        self.receiver.lock().take()
+    }
+
+    pub fn evaluate(&self, log: &LogModel) {
+        let now = Instant::now();
+        self.prune(now);
+        for (index, rule) in self.rules.iter().enumerate() {
+            if !rule.matcher.matches(log)
+                || !rule
+                    .regex
+                    .as_ref()
+                    .is_none_or(|regex| regex.is_match(&log.content))
+            {
+                continue;
+            }
+            let key = (index, rule.per_source.then(|| log.source.to_owned()));
+            let count = {
+                let mut state = self.state.lock();
+                let state = state.rules.entry(key).or_default();
+                let count = match rule.threshold {
+                    Some((threshold, window)) => {
+                        state.recent.push_back(now);
+                        while state
+                            .recent
+                            .front()
+                            .is_some_and(|written| now.duration_since(*written) > window)
+                        {
+                            state.recent.pop_front();
+                        }
+                        if state.recent.len() <= threshold {
+                            continue;
+                        }
+                        state.recent.len()
+                    }
+                    None => 1,
+                };
+                if state
+                    .last_fired
+                    .is_some_and(|last_fired| now.duration_since(last_fired) < rule.cooldown)
+                {
+                    continue;
+                }
+                state.last_fired = Some(now);
+                count
+            };
+            let alert = Alert {
+                rule: rule.name.to_owned(),
+                source: log.source.to_owned(),
+                level: Level::try_from(log.level).unwrap_or(Level::Error),
+                timestamp: micros_to_timestamp(log.timestamp).unwrap_or_default(),
+                location: log.location.to_owned(),
+                content: log.content.to_owned(),
+                count,
+            };
+            //Only fails once the alert task has stopped along with the manager
+            let _ = self.sender.send((index, alert));
+        }
+    }
+
+    ///Drops expired state every PRUNE_INTERVAL, per_source rules would otherwise keep state for
+    ///every source they have ever matched
+    fn prune(&self, now: Instant) {
+        let mut state = self.state.lock();
+        if now.duration_since(state.last_pruned) < PRUNE_INTERVAL {
+            return;
+        }
+        state.last_pruned = now;
+        state.rules.retain(|(index, _), rule_state| {
+            self.rules
+                .get(*index)
+                .is_some_and(|rule| !rule_state.is_expired(rule, now))
+        });
+    }
+
+    ///Runs every action of the rule which fired
+    pub async fn dispatch(&self, index: usize, alert: Alert<String>) {
+        let Some(rule) = self.rules.get(index) else {
+            return;
+        };
+        for action in rule.actions.iter() {
+            match action {
+                Action::Callback(callback) => callback(&alert),
+                Action::File(path) => {
+                    let path = path.to_owned();
+                    let line = match alert_json(&alert) {
+                        Some(line) => line,
+                        None => continue,
+                    };
+                    let result = tokio::task::spawn_blocking(move || {
+                        OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(&path)
+                            .and_then(|mut file| writeln!(file, "{line}"))
+                            .map_err(|err| format!("{}: {err}", path.display()))
+                    })
+                    .await;
+                    match result {
+                        Ok(Ok(())) => {}
+                        Ok(Err(err)) => error!("Failed to write alert to {err}"),
+                        Err(err) => error!("Alert file writer panicked: {err}"),
+                    }
+                }
+                #[cfg(feature = "webhook")]
+                Action::Webhook(url) => {
+                    let Some(body) = alert_json(&alert) else {
+                        continue;
+                    };
+                    let result = self
+                        .http
+                        .post(url)
+                        .header(reqwest::header::CONTENT_TYPE, "application/json")
+                        .body(body)
+                        .send()
+                        .await
+                        .and_then(|response| response.error_for_status());
+                    if let Err(err) = result {
+                        error!("Failed to POST alert to {url}: {err}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+///The alert with its source embedded as JSON rather than as a string
+fn alert_json(alert: &Alert<String>) -> Option<String> {
+    let encoded = alert
+        .decode::<serde_json::Value>()
+        .and_then(|alert| serde_json::to_string(&alert));
+    match encoded {
+        Ok(encoded) => Some(encoded),
+        Err(err) => {
+            error!("Failed to encode alert for rule {}: {err}", alert.rule);
+            None
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    marker::PhantomData,
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use diesel::{
+    dsl::count_star, ExpressionMethods, Insertable, QueryDsl, Queryable, RunQueryDsl,
+    SqliteConnection,
+};
+use parking_lot::Mutex;
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    database::{
+        configure_connection, establish_connection, run_migrations, ConnectionOptions,
+        CLIENT_MIGRATIONS,
+    },
+    error::{BuilderError, DieselResultError, Error, ReqwestError, SerdeError},
+    ingest::{IngestBatch, IngestLog},
+    logs::{timestamp_to_micros, SimpleLog},
+    serialize_or_return_err,
+};
+
+diesel::table! {
+    outbox (id) {
+        id -> Integer,
+        ingest_id -> Text,
+        source -> Text,
+        log -> Text,
+    }
+}
+
+#[derive(Queryable)]
+#[diesel(table_name = outbox)]
+struct OutboxEntry {
+    id: i32,
+    ingest_id: String,
+    source: String,
+    log: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = outbox)]
+struct NewOutboxEntry {
+    ingest_id: String,
+    source: String,
+    log: String,
+}
+
+#[derive(Debug)]
+pub enum RequiredProperties {
+    ServerUrl,
+    BufferUrl,
+}
+
+pub struct Builder {
+    //required
+    server_url: Option<String>,
+    buffer_url: Option<String>,
+
+    //optional
+    stop: Option<Arc<AtomicBool>>,
+    stop_notify: Option<Arc<Notify>>,
+    //defaulted
+    batch_size: usize,
+    retry_interval: Duration,
+    max_retry_interval: Duration,
+    request_timeout: Duration,
+    connection_options: ConnectionOptions,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            server_url: None,
+            buffer_url: None,
+            stop: None,
+            stop_notify: None,
+            batch_size: 256,
+            retry_interval: Duration::from_secs(5),
+            max_retry_interval: Duration::from_secs(300),
+            request_timeout: Duration::from_secs(30),
+            connection_options: ConnectionOptions::default(),
+        }
+    }
+}
+
+impl Builder {
+    pub fn stop(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    pub fn stop_notify(mut self, stop_notify: Arc<Notify>) -> Self {
+        self.stop_notify = Some(stop_notify);
+        self
+    }
+
+    ///Base url of a LogManager serving the http module, e.g. `http://logs.internal:8080`
+    pub fn server_url(mut self, server_url: String) -> Self {
+        self.server_url = Some(server_url);
+        self
+    }
+
+    ///SQLite database holding logs until the server has them, survives restarts when on disk
+    pub fn buffer_url(mut self, buffer_url: String) -> Self {
+        self.buffer_url = Some(buffer_url);
+        self
+    }
+
+    ///Maximum number of logs sent in a single request
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    ///How long to wait before trying again after the server couldn't be reached or failed the request,
+    ///doubled after every consecutive failure up to max_retry_interval
+    pub fn retry_interval(mut self, retry_interval: Duration) -> Self {
+        self.retry_interval = retry_interval;
+        self
+    }
+
+    pub fn max_retry_interval(mut self, max_retry_interval: Duration) -> Self {
+        self.max_retry_interval = max_retry_interval;
+        self
+    }
+
+    pub fn request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    ///Settings for the buffer database connection
+    pub fn connection_options(mut self, connection_options: ConnectionOptions) -> Self {
+        self.connection_options = connection_options;
+        self
+    }
+
+    pub async fn build<S: Serialize + Send + Sync + 'static>(
+        mut self,
+    ) -> Result<Arc<Client<S>>, Error> {
+        let mut missing_properties: Vec<RequiredProperties> = Vec::new();
+        if self.server_url.is_none() {
+            missing_properties.push(RequiredProperties::ServerUrl);
+        }
+        if self.buffer_url.is_none() {
+            missing_properties.push(RequiredProperties::BufferUrl);
+        }
+        if !missing_properties.is_empty() {
+            return Err(Error::Builder(BuilderError::MissingProperties(format!(
+                "{:?}",
+                missing_properties
+            ))));
+        }
+
+        let stop: Arc<AtomicBool> = self.stop.take().unwrap_or(Arc::new(AtomicBool::new(false)));
+        let stop_notify: Arc<Notify> = self.stop_notify.take().unwrap_or(Arc::new(Notify::new()));
+        let server_url: String = self.server_url.take().unwrap();
+        let buffer_url: String = self.buffer_url.take().unwrap();
+
+        let client: Arc<Client<S>> =
+            Client::<S>::new(stop, stop_notify, server_url, buffer_url, self)?;
+        tokio::task::spawn(Client::run_shipper(client.to_owned()));
+
+        Ok(client)
+    }
+}
+
+///Ships logs to a remote LogManager, keeping them in a local buffer until the server confirms
+///it has written them. Delivery is at-least-once, every log carries an id the server uses to
+///discard copies it already has, so a log is stored once even if a request has to be repeated.
+///Logs the server rejects (e.g. while its source type doesn't match S) are kept and retried with
+///the same backoff as an unreachable server, holding back later logs until they are accepted.
+pub struct Client<S: Serialize> {
+    stop: Arc<AtomicBool>,
+    stop_notify: Arc<Notify>,
+    connection: Arc<Mutex<SqliteConnection>>,
+    connection_options: ConnectionOptions,
+    http: reqwest::Client,
+    logs_url: String,
+    batch_size: usize,
+    retry_interval: Duration,
+    max_retry_interval: Duration,
+    ///Held while a batch is in flight so the shipper task and flush don't send the same logs
+    shipping: tokio::sync::Mutex<()>,
+    saved: Notify,
+    _phantom: PhantomData<S>,
+}
+
+impl<S: Serialize + Send + Sync + 'static> Client<S> {
+    fn new(
+        stop: Arc<AtomicBool>,
+        stop_notify: Arc<Notify>,
+        server_url: String,
+        buffer_url: String,
+        options: Builder,
+    ) -> Result<Arc<Self>, Error> {
+        let mut connection = establish_connection(&buffer_url)?;
+        options
+            .connection_options
+            .busy_retry
+            .run(|| configure_connection(&mut connection, &options.connection_options))?;
+        if let Err(err) = connection
+            .immediate_transaction(|connection| run_migrations(connection, CLIENT_MIGRATIONS))
+        {
+            return Err(Error::RunningMigrations(err.to_string()));
+        }
+        let http = reqwest::Client::builder()
+            .timeout(options.request_timeout)
+            .build()
+            .map_err(|err| Error::Request(ReqwestError(err)))?;
+        Ok(Arc::new(Self {
+            stop,
+            stop_notify,
+            connection: Arc::new(Mutex::new(connection)),
+            connection_options: options.connection_options,
+            http,
+            logs_url: format!("{}/logs", server_url.trim_end_matches('/')),
+            batch_size: options.batch_size.max(1),
+            retry_interval: options.retry_interval,
+            max_retry_interval: options.max_retry_interval,
+            shipping: tokio::sync::Mutex::new(()),
+            saved: Notify::new(),
+            _phantom: PhantomData,
+        }))
+    }
+
+    async fn run_shipper(client: Arc<Self>) {
+        let mut failures: u32 = 0;
+        while !client.stop.load(Ordering::SeqCst) {
+            let mut stop_notified = pin!(client.stop_notify.notified());
+            stop_notified.as_mut().enable();
+            let wait = match client.ship_pending().await {
+                Ok(_) => {
+                    failures = 0;
+                    None
+                }
+                Err(err) => {
+                    let retry_interval = client
+                        .retry_interval
+                        .saturating_mul(2u32.saturating_pow(failures))
+                        .min(client.max_retry_interval);
+                    failures = failures.saturating_add(1);
+                    warn!("Failed to ship logs, retrying in {retry_interval:?}: {err}");
+                    Some(retry_interval)
+                }
+            };
+            match wait {
+                Some(retry_interval) => tokio::select! {
+                    _ = stop_notified => {},
+                    _ = tokio::time::sleep(retry_interval) => {},
+                },
+                None => tokio::select! {
+                    _ = stop_notified => {},
+                    _ = client.saved.notified() => {},
+                },
+            }
+        }
+        info!("Log client shipper stopped");
+    }
+
+    ///Stores the log in the buffer, it is sent in the background as soon as the server is reachable
+    pub fn save_log(&self, log: SimpleLog, source: S) -> Result<(), Error> {
+        //The server would reject it, better to fail here than to drop it later
+        timestamp_to_micros(&log.timestamp)?;
+        let entry = NewOutboxEntry {
+            ingest_id: Uuid::new_v4().to_string(),
+            source: serialize_or_return_err!(&source, "source"),
+            log: serialize_or_return_err!(&log, "log"),
+        };
+        self.connection_options.busy_retry.run(|| {
+            diesel::insert_into(outbox::table)
+                .values(&entry)
+                .execute(&mut *self.connection.lock())
+                .map_err(|err| Error::DieselResult(DieselResultError(err)))
+        })?;
+        self.saved.notify_one();
+        Ok(())
+    }
+
+    ///Number of logs in the buffer which the server hasn't confirmed yet
+    pub fn pending(&self) -> Result<i64, Error> {
+        outbox::table
+            .select(count_star())
+            .first::<i64>(&mut *self.connection.lock())
+            .map_err(|err| Error::DieselResult(DieselResultError(err)))
+    }
+
+    ///Sends everything in the buffer now, fails if the server can't be reached
+    pub async fn flush(&self) -> Result<(), Error> {
+        self.ship_pending().await.map(|_| ())
+    }
+
+    ///Sends batches oldest first until the buffer is empty, returning the number of logs shipped.
+    ///Batches the server finds too large are split in half until they fit.
+    async fn ship_pending(&self) -> Result<usize, Error> {
+        let _shipping = self.shipping.lock().await;
+        let mut shipped = 0;
+        let mut batch_size = self.batch_size;
+        loop {
+            let entries = self
+                .run_blocking(move |connection| next_batch(connection, batch_size))
+                .await?;
+            let Some(last) = entries.last().map(|entry| entry.id) else {
+                return Ok(shipped);
+            };
+            let batch = IngestBatch {
+                source: decode::<serde_json::Value>(&entries[0].source, "source")?,
+                logs: entries
+                    .iter()
+                    .map(|entry| {
+                        Ok(IngestLog {
+                            log: decode(&entry.log, "log")?,
+                            id: Some(entry.ingest_id.to_owned()),
+                        })
+                    })
+                    .collect::<Result<Vec<IngestLog>, Error>>()?,
+            };
+            let response = self
+                .http
+                .post(&self.logs_url)
+                .json(&batch)
+                .send()
+                .await
+                .map_err(|err| Error::Request(ReqwestError(err)))?;
+            let status = response.status();
+            if status == StatusCode::PAYLOAD_TOO_LARGE && entries.len() > 1 {
+                batch_size = entries.len() / 2;
+                continue;
+            }
+            if !status.is_success() {
+                //Logs are only removed from the buffer once the server has them, even when it
+                //rejects them, a later version of the server may accept them
+                let body = response.text().await.unwrap_or_default();
+                return Err(Error::ServerResponse(status.as_u16(), body));
+            }
+            self.run_blocking(move |connection| {
+                diesel::delete(outbox::table.filter(outbox::id.le(last)))
+                    .execute(connection)
+                    .map_err(|err| Error::DieselResult(DieselResultError(err)))
+            })
+            .await?;
+            shipped += entries.len();
+        }
+    }
+
+    ///Runs operation against the buffer on tokio's blocking thread pool, retrying while the
+    ///database is busy, so neither SQLite I/O nor the backoff hold up async workers
+    async fn run_blocking<T: Send + 'static>(
+        &self,
+        mut operation: impl FnMut(&mut SqliteConnection) -> Result<T, Error> + Send + 'static,
+    ) -> Result<T, Error> {
+        let connection = self.connection.to_owned();
+        let busy_retry = self.connection_options.busy_retry;
+        tokio::task::spawn_blocking(move || busy_retry.run(|| operation(&mut connection.lock())))
+            .await
+            .map_err(|err| Error::BlockingTask(err.to_string()))?
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.stop_notify.notify_waiters();
+    }
+}
+
+///Oldest logs in the buffer, cut short where the source changes since a batch has one source
+fn next_batch(
+    connection: &mut SqliteConnection,
+    batch_size: usize,
+) -> Result<Vec<OutboxEntry>, Error> {
+    let mut entries = outbox::table
+        .order(outbox::id.asc())
+        .limit(batch_size as i64)
+        .load::<OutboxEntry>(connection)
+        .map_err(|err| Error::DieselResult(DieselResultError(err)))?;
+    if let Some(first) = entries.first().map(|entry| entry.source.to_owned()) {
+        let same_source = entries
+            .iter()
+            .take_while(|entry| entry.source == first)
+            .count();
+        entries.truncate(same_source);
+    }
+    Ok(entries)
+}
+
+///Buffered fields are forwarded as they were serialized, S doesn't need to be deserializable
+fn decode<T: DeserializeOwned>(value: &str, field_name: &str) -> Result<T, Error> {
+    serde_json::from_str(value)
+        .map_err(|err| Error::DeserializingField(field_name.to_string(), SerdeError(err)))
+}
@@ -1,13 +1,22 @@
 pub mod model;
+pub mod pool;
 
-use diesel::{sqlite::Sqlite, Connection, SqliteConnection};
+use diesel::{
+    connection::SimpleConnection,
+    result::{DatabaseErrorKind, Error as DieselError},
+    sqlite::Sqlite,
+    Connection, SqliteConnection,
+};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use std::error::Error as StdError;
-use tracing::error;
+use std::{error::Error as StdError, time::Duration};
+use tracing::{error, warn};
 
-use crate::error::{DieselConnectionError, Error};
+use crate::error::{DieselConnectionError, DieselResultError, Error};
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+///Schema of the buffer kept by client::Client
+#[cfg(feature = "client")]
+pub const CLIENT_MIGRATIONS: EmbeddedMigrations = embed_migrations!("client_migrations");
 
 pub fn establish_connection(database_url: &str) -> Result<SqliteConnection, Error> {
     match SqliteConnection::establish(database_url) {
@@ -19,6 +28,97 @@ pub fn establish_connection(database_url: &str) -> Result<SqliteConnection, Erro
     }
 }
 
+///Settings applied to every connection opened by a LogManager
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionOptions {
+    ///Write-ahead logging lets readers carry on while another connection or process is writing
+    pub wal: bool,
+    ///How long SQLite itself waits on a lock held by another connection before giving up with SQLITE_BUSY
+    pub busy_timeout: Duration,
+    pub busy_retry: BusyRetry,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            wal: true,
+            busy_timeout: Duration::from_secs(5),
+            busy_retry: BusyRetry::default(),
+        }
+    }
+}
+
+///Retries writes which still fail with SQLITE_BUSY after the busy timeout, doubling the backoff
+///between attempts up to max_backoff
+#[derive(Debug, Clone, Copy)]
+pub struct BusyRetry {
+    ///Includes the first attempt, 1 disables retrying
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for BusyRetry {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl BusyRetry {
+    ///Blocks the calling thread while backing off
+    pub fn run<T>(&self, mut operation: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match operation() {
+                Err(err) if attempt < self.max_attempts && is_busy(&err) => {
+                    warn!(
+                        "Database busy, retrying in {}ms (attempt {attempt})",
+                        backoff.as_millis()
+                    );
+                    std::thread::sleep(backoff);
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn is_busy(err: &Error) -> bool {
+    match err {
+        Error::DieselResult(DieselResultError(DieselError::DatabaseError(
+            DatabaseErrorKind::Unknown,
+            info,
+        ))) => info.message().starts_with("database is locked"),
+        _ => false,
+    }
+}
+
+pub fn configure_connection(
+    connection: &mut SqliteConnection,
+    options: &ConnectionOptions,
+) -> Result<(), Error> {
+    //busy_timeout goes first, switching to WAL needs a lock of its own
+    let mut pragmas = format!(
+        "PRAGMA busy_timeout = {};",
+        options.busy_timeout.as_millis()
+    );
+    if options.wal {
+        pragmas.push_str(" PRAGMA journal_mode = WAL;");
+    }
+    connection.batch_execute(&pragmas).map_err(|err| {
+        let err = Error::DieselResult(DieselResultError(err));
+        error!("Error configuring connection. Err: {err}");
+        err
+    })
+}
+
 pub fn run_migrations(
     connection: &mut impl MigrationHarness<Sqlite>,
     embedded_migrations: EmbeddedMigrations,
@@ -1,11 +1,9 @@
 use diesel::{Identifiable, Insertable, Queryable};
-use serde::de::DeserializeOwned;
-use serde::Serialize;
-use std::sync::atomic::Ordering;
+use serde::{Deserialize, Serialize};
 
 use crate::error::Error;
+use crate::logs::{timestamp_to_micros, SimpleLog};
 use crate::schema::log;
-use crate::{logs::SimpleLog, NEXT_LOG_ID};
 
 #[macro_export]
 macro_rules! serialize_or_return_err {
@@ -13,9 +11,9 @@ macro_rules! serialize_or_return_err {
         match serde_json::to_string(&$t) {
             Ok(t) => t,
             Err(err) => {
-                let err = crate::error::Error::SerializingField(
+                let err = $crate::error::Error::SerializingField(
                     $field_name.to_string(),
-                    crate::error::SerdeError(err),
+                    $crate::error::SerdeError(err),
                 );
                 tracing::warn!("Error serializing field {}: {err}", $field_name);
                 return Err(err);
@@ -24,30 +22,81 @@ macro_rules! serialize_or_return_err {
     };
 }
 
-#[derive(Insertable, Queryable, Identifiable)]
+#[derive(Queryable, Identifiable, Serialize, Deserialize, Debug, Clone)]
 #[diesel(primary_key(id))]
 #[diesel(table_name = log)]
 pub struct LogModel {
     pub id: i32,
     pub source: String,
-    pub timestamp: String,
-    pub level: String,
+    ///Microseconds since the unix epoch
+    pub timestamp: i64,
+    pub level: i32,
     pub location: String,
     pub content: String,
+    ///Set when the log was sent with an idempotency key, see ingest::IngestLog
+    pub ingest_id: Option<String>,
+    ///JSON object of logs::Fields, None when the log has no fields
+    pub fields: Option<String>,
+    ///See logs::SpanContext, the other span columns are only set along with trace_id
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    pub parent_span_id: Option<String>,
+    pub span_name: Option<String>,
+    ///Encoded source path, None until backfilled for logs written before it was stored
+    pub source_path: Option<String>,
+    ///Written by the heartbeat monitor, see Builder::heartbeat
+    pub heartbeat: bool,
 }
 
-impl LogModel {
-    pub fn from<S: Serialize + DeserializeOwned>(
-        value: SimpleLog,
-        source: S,
-    ) -> Result<Self, Error> {
+///A log which has not been written yet, its id is assigned by SQLite on insert
+#[derive(Insertable)]
+#[diesel(table_name = log)]
+pub struct NewLogModel {
+    pub source: String,
+    ///Microseconds since the unix epoch
+    pub timestamp: i64,
+    pub level: i32,
+    pub location: String,
+    pub content: String,
+    pub ingest_id: Option<String>,
+    pub fields: Option<String>,
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    pub parent_span_id: Option<String>,
+    pub span_name: Option<String>,
+    pub source_path: Option<String>,
+    pub heartbeat: bool,
+}
+
+impl NewLogModel {
+    pub fn from<S: Serialize>(value: SimpleLog, source: S) -> Result<Self, Error> {
+        let (trace_id, span_id, parent_span_id, span_name) = match value.span {
+            Some(span) => (
+                Some(span.trace_id),
+                span.span_id,
+                span.parent_span_id,
+                span.span_name,
+            ),
+            None => (None, None, None, None),
+        };
         Ok(Self {
-            id: NEXT_LOG_ID.fetch_add(1, Ordering::SeqCst) as i32,
             source: serialize_or_return_err!(&source, "source"),
-            timestamp: serialize_or_return_err!(&value.timestamp, "timestamp"),
-            level: serialize_or_return_err!(&value.level, "level"),
-            location: serialize_or_return_err!(&value.location, "location"),
-            content: serialize_or_return_err!(&value.content, "content"),
+            timestamp: timestamp_to_micros(&value.timestamp)?,
+            level: value.level as i32,
+            location: value.location,
+            content: value.content,
+            ingest_id: None,
+            fields: match value.fields.is_empty() {
+                true => None,
+                false => Some(serialize_or_return_err!(&value.fields, "fields")),
+            },
+            trace_id,
+            span_id,
+            parent_span_id,
+            span_name,
+            //Filled in by the manager, which knows how paths are built
+            source_path: None,
+            heartbeat: false,
         })
     }
 }
@@ -0,0 +1,88 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use diesel::SqliteConnection;
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::{
+    database::{configure_connection, establish_connection, BusyRetry, ConnectionOptions},
+    error::Error,
+};
+
+///Long-lived connections owned by a LogManager: a single writer and a set of readers
+pub struct ConnectionPool {
+    writer: Mutex<SqliteConnection>,
+    readers: Vec<Mutex<SqliteConnection>>,
+    next_reader: AtomicUsize,
+    busy_retry: BusyRetry,
+}
+
+impl ConnectionPool {
+    pub fn new(
+        database_url: &str,
+        reader_count: usize,
+        options: &ConnectionOptions,
+    ) -> Result<Self, Error> {
+        let open = || -> Result<SqliteConnection, Error> {
+            let mut connection = establish_connection(database_url)?;
+            //Switching to WAL can report the database as locked without waiting out the busy timeout
+            //while other processes are opening it
+            options
+                .busy_retry
+                .run(|| configure_connection(&mut connection, options))?;
+            Ok(connection)
+        };
+        let writer = Mutex::new(open()?);
+        //Every connection to an in-memory or temporary database gets its own private database,
+        //so all reads have to go through the writer to see anything
+        let reader_count = if is_private(database_url) {
+            0
+        } else {
+            reader_count
+        };
+        let mut readers = Vec::with_capacity(reader_count);
+        for _ in 0..reader_count {
+            readers.push(Mutex::new(open()?));
+        }
+        Ok(Self {
+            writer,
+            readers,
+            next_reader: AtomicUsize::new(0),
+            busy_retry: options.busy_retry,
+        })
+    }
+
+    pub fn writer(&self) -> MutexGuard<'_, SqliteConnection> {
+        self.writer.lock()
+    }
+
+    ///Retry policy for writes which fail because another connection holds the lock
+    pub fn busy_retry(&self) -> &BusyRetry {
+        &self.busy_retry
+    }
+
+    ///Returns the first idle reader, otherwise waits on the next one in round-robin order.
+    ///Falls back to the writer when the pool has no readers.
+    pub fn reader(&self) -> MutexGuard<'_, SqliteConnection> {
+        if self.readers.is_empty() {
+            return self.writer();
+        }
+        let start = self.next_reader.fetch_add(1, Ordering::Relaxed);
+        for offset in 0..self.readers.len() {
+            if let Some(guard) = self.readers[(start + offset) % self.readers.len()].try_lock() {
+                return guard;
+            }
+        }
+        self.readers[start % self.readers.len()].lock()
+    }
+}
+
+///An empty url opens a temporary database, urls are interpreted as URIs when they start with "file:"
+fn is_private(database_url: &str) -> bool {
+    let Some(uri) = database_url.strip_prefix("file:") else {
+        return database_url.is_empty() || database_url == ":memory:";
+    };
+    let (path, query) = uri.split_once('?').unwrap_or((uri, ""));
+    path.is_empty()
+        || path == ":memory:"
+        || query.split('&').any(|parameter| parameter == "mode=memory")
+}
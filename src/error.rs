@@ -4,6 +4,10 @@ use thiserror::Error;
 impl_error_wrapper!(DieselConnectionError, diesel::result::ConnectionError);
 impl_error_wrapper!(DieselResultError, diesel::result::Error);
 impl_error_wrapper!(SerdeError, serde_json::error::Error);
+impl_error_wrapper!(ChronoParseError, chrono::ParseError);
+impl_error_wrapper!(IoError, std::io::Error);
+#[cfg(feature = "client")]
+impl_error_wrapper!(ReqwestError, reqwest::Error);
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -17,10 +21,45 @@ pub enum Error {
     SerializingField(String, SerdeError),
     #[error("DeserializingField({0}, {1})")]
     DeserializingField(String, SerdeError),
+    #[error("ParsingTimestamp({0}, {1})")]
+    ParsingTimestamp(String, ChronoParseError),
+    #[error("TimestampOutOfRange({0})")]
+    TimestampOutOfRange(i64),
+    #[error("InvalidLevel({0})")]
+    InvalidLevel(i32),
+    #[error("ParsingLevel({0})")]
+    ParsingLevel(String),
+    #[error("InvalidCursor({0})")]
+    InvalidCursor(String),
+    #[error("InvalidPagination({0})")]
+    InvalidPagination(String),
+    #[error("InvalidFieldKey({0})")]
+    InvalidFieldKey(String),
+    #[error("InvalidFullTextQuery({0})")]
+    InvalidFullTextQuery(String),
+    #[error("UnsupportedFilter({0})")]
+    UnsupportedFilter(String),
+    #[error("SubscriptionLagged({0})")]
+    SubscriptionLagged(u64),
+    #[error("SubscriptionClosed")]
+    SubscriptionClosed,
+    #[error("BindingHttpListener({0}, {1})")]
+    BindingHttpListener(String, IoError),
+    #[cfg(feature = "client")]
+    #[error("Request({0})")]
+    Request(ReqwestError),
+    #[error("ServerResponse({0}, {1})")]
+    ServerResponse(u16, String),
     #[error("Builder({0})")]
     Builder(BuilderError),
-    #[error("NegativeLogID({0})")]
-    NegativeLogID(i32),
+    #[error("BlockingTask({0})")]
+    BlockingTask(String),
+    #[error("WriterStopped")]
+    WriterStopped,
+    #[error("LogsNotWritten")]
+    LogsNotWritten,
+    #[error("WrittenCountMismatch({0}, {1})")]
+    WrittenCountMismatch(usize, usize),
     #[error("Errors({:?})", 0)]
     Errors(Vec<Self>),
 }
@@ -29,4 +68,6 @@ pub enum Error {
 pub enum BuilderError {
     #[error("MissingProperties({0})")]
     MissingProperties(String),
+    #[error("SourceTypeMismatch({0})")]
+    SourceTypeMismatch(String),
 }
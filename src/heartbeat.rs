@@ -0,0 +1,252 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use chrono::{TimeDelta, Utc};
+use diesel::{
+    dsl, ExpressionMethods, NullableExpressionMethods, QueryDsl, RunQueryDsl, SqliteConnection,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    database::model::NewLogModel,
+    error::{DieselResultError, Error},
+    logs::{micros_to_timestamp, Level},
+    schema::log::dsl::{
+        heartbeat as heartbeat_db, log as log_data, source as source_db, timestamp as timestamp_db,
+    },
+};
+
+///Location of the logs written by the monitor, which are marked so they don't count as activity of
+///their source, see SearchFilter::heartbeat
+pub const HEARTBEAT_LOCATION: &str = "log_manager::heartbeat";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeartbeatStatus {
+    ///Nothing logged within the window
+    Silent,
+    ///Logged again after being silent
+    Recovered,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HeartbeatEvent<S> {
+    pub source: S,
+    pub status: HeartbeatStatus,
+    ///RFC3339 timestamp of the newest log from the source, None if it has never logged
+    pub last_seen: Option<String>,
+}
+
+type Callback = Box<dyn Fn(HeartbeatStatus, &str, Option<i64>) + Send + Sync>;
+
+///Watches for sources which stop logging, see Builder::heartbeat.
+///A source is silent once window has passed since its newest log and since the monitor started,
+///so restarting the manager gives every source a full window to check in.
+pub struct HeartbeatMonitor<S> {
+    window: Duration,
+    check_interval: Duration,
+    expected: Vec<S>,
+    track_seen_sources: bool,
+    emit_logs: bool,
+    callback: Option<Box<dyn Fn(HeartbeatEvent<S>) + Send + Sync>>,
+}
+
+impl<S: Serialize + DeserializeOwned + 'static> HeartbeatMonitor<S> {
+    ///Checks every 30 seconds and writes an Error log when a source goes silent,
+    ///and an Info log when it recovers
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            check_interval: Duration::from_secs(30),
+            expected: Vec::new(),
+            track_seen_sources: false,
+            emit_logs: true,
+            callback: None,
+        }
+    }
+
+    ///A source which should be logging, including ones which never have
+    pub fn expect(mut self, source: S) -> Self {
+        self.expected.push(source);
+        self
+    }
+
+    ///Also watches every source with logs stored, for fleets whose sources aren't known up front
+    pub fn track_seen_sources(mut self, track_seen_sources: bool) -> Self {
+        self.track_seen_sources = track_seen_sources;
+        self
+    }
+
+    pub fn check_interval(mut self, check_interval: Duration) -> Self {
+        self.check_interval = check_interval;
+        self
+    }
+
+    ///Whether a log attributed to the source is written when it goes silent or recovers
+    pub fn emit_logs(mut self, emit_logs: bool) -> Self {
+        self.emit_logs = emit_logs;
+        self
+    }
+
+    ///Called from the monitor task for every status change, shouldn't block
+    pub fn callback(
+        mut self,
+        callback: impl Fn(HeartbeatEvent<S>) + Send + Sync + 'static,
+    ) -> Self {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    ///Drops S so the monitor can be held by the Builder, sources are kept as stored in the database
+    pub(crate) fn into_heartbeat(self) -> Heartbeat {
+        let expected = self
+            .expected
+            .iter()
+            .filter_map(|source| match serde_json::to_string(source) {
+                Ok(source) => Some(source),
+                Err(err) => {
+                    warn!("Not watching source which failed to serialize: {err}");
+                    None
+                }
+            })
+            .collect();
+        let callback = self.callback.map(|callback| -> Callback {
+            Box::new(move |status, source, last_seen| {
+                let source = match serde_json::from_str(source) {
+                    Ok(source) => source,
+                    Err(err) => {
+                        warn!("Skipping heartbeat callback for source {source}: {err}");
+                        return;
+                    }
+                };
+                callback(HeartbeatEvent {
+                    source,
+                    status,
+                    last_seen: last_seen.and_then(|last_seen| micros_to_timestamp(last_seen).ok()),
+                })
+            })
+        });
+        Heartbeat {
+            window: self.window,
+            check_interval: self.check_interval,
+            expected,
+            track_seen_sources: self.track_seen_sources,
+            emit_logs: self.emit_logs,
+            callback,
+        }
+    }
+}
+
+///HeartbeatMonitor with sources as JSON
+pub(crate) struct Heartbeat {
+    window: Duration,
+    pub check_interval: Duration,
+    expected: Vec<String>,
+    track_seen_sources: bool,
+    emit_logs: bool,
+    callback: Option<Callback>,
+}
+
+impl Heartbeat {
+    ///Timestamp of the newest log from each watched source, excluding the monitor's own logs
+    pub fn last_seen(
+        &self,
+        connection: &mut SqliteConnection,
+    ) -> Result<HashMap<String, i64>, Error> {
+        let mut query = log_data
+            .filter(heartbeat_db.eq(false))
+            .group_by(source_db)
+            .select((source_db, dsl::max(timestamp_db).assume_not_null()))
+            .into_boxed();
+        if !self.track_seen_sources {
+            query = query.filter(source_db.eq_any(self.expected.to_owned()));
+        }
+        let rows = query
+            .load::<(String, i64)>(connection)
+            .map_err(|err| Error::DieselResult(DieselResultError(err)))?;
+        Ok(rows.into_iter().collect())
+    }
+
+    ///Compares last_seen against the window, updating silent and returning the logs to write
+    pub fn check(
+        &self,
+        last_seen: &HashMap<String, i64>,
+        started: i64,
+        silent: &mut HashSet<String>,
+    ) -> Vec<NewLogModel> {
+        let now = Utc::now().timestamp_micros();
+        let window = TimeDelta::from_std(self.window)
+            .unwrap_or(TimeDelta::MAX)
+            .num_microseconds()
+            .unwrap_or(i64::MAX);
+        let mut sources: HashSet<&String> = self.expected.iter().collect();
+        sources.extend(last_seen.keys());
+        let mut logs = Vec::new();
+        for source in sources {
+            let seen = last_seen.get(source).copied();
+            let active_since = seen.unwrap_or(started).max(started);
+            let is_silent = now.saturating_sub(active_since) > window;
+            let status = match (is_silent, silent.contains(source)) {
+                (true, false) => {
+                    silent.insert(source.to_owned());
+                    HeartbeatStatus::Silent
+                }
+                (false, true) => {
+                    silent.remove(source);
+                    HeartbeatStatus::Recovered
+                }
+                _ => continue,
+            };
+            if let Some(callback) = &self.callback {
+                callback(status, source, seen);
+            }
+            if self.emit_logs {
+                logs.push(self.log(status, source, seen, now));
+            }
+        }
+        logs
+    }
+
+    fn log(
+        &self,
+        status: HeartbeatStatus,
+        source: &str,
+        seen: Option<i64>,
+        now: i64,
+    ) -> NewLogModel {
+        let last_seen = seen
+            .and_then(|seen| micros_to_timestamp(seen).ok())
+            .unwrap_or_else(|| "never".into());
+        let (level, content) = match status {
+            HeartbeatStatus::Silent => (
+                Level::Error,
+                format!(
+                    "Source has not logged within {:?}, last seen {last_seen}",
+                    self.window
+                ),
+            ),
+            HeartbeatStatus::Recovered => (
+                Level::Info,
+                format!("Source is logging again, last seen {last_seen}"),
+            ),
+        };
+        NewLogModel {
+            source: source.to_owned(),
+            timestamp: now,
+            level: level as i32,
+            location: HEARTBEAT_LOCATION.into(),
+            content,
+            ingest_id: None,
+            fields: None,
+            trace_id: None,
+            span_id: None,
+            parent_span_id: None,
+            span_name: None,
+            //Set by the manager which knows how to decode the source
+            source_path: None,
+            heartbeat: true,
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use std::{net::SocketAddr, ops::Bound, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tracing::{error, info};
+
+use crate::{
+    database::model::NewLogModel,
+    error::{ChronoParseError, Error, IoError, SerdeError},
+    ingest::{IngestBatch, IngestResponse},
+    logs::{Fields, Level},
+    manager::{LogManager, Pagination},
+    search::{Highlight, SearchFilter, SearchResults, Sort, SortDirection, SortField},
+};
+
+///Number of logs returned by GET /logs when no page_size or limit is given
+pub const DEFAULT_LIMIT: usize = 100;
+
+///Most logs returned by one GET /logs request, larger page_size and limit values are lowered to it
+pub const MAX_LIMIT: usize = 1000;
+
+///Query string of GET /logs, every parameter is optional.
+///source is S encoded as JSON, source_prefix is a JSON array of path components e.g. `["Agent"]`,
+///levels is comma separated e.g. `warn,error`,
+///from is inclusive and to is exclusive, both RFC3339.
+///page selects page based pagination, otherwise cursor pagination with after or before,
+///limit and page_size are interchangeable, default to DEFAULT_LIMIT and are capped at MAX_LIMIT.
+///fields is a JSON object of structured fields which have to be equal, e.g. `{"status":500}`.
+#[derive(Deserialize, Debug, Default)]
+struct SearchParams {
+    source: Option<String>,
+    source_prefix: Option<String>,
+    levels: Option<String>,
+    min_level: Option<String>,
+    content: Option<String>,
+    full_text: Option<String>,
+    ///Returns snippets of the full_text matches using Highlight::default
+    highlight: Option<bool>,
+    from: Option<String>,
+    to: Option<String>,
+    fields: Option<String>,
+    trace_id: Option<String>,
+    ///true for only the logs written by the heartbeat monitor, false to leave them out
+    heartbeat: Option<bool>,
+    sort: Option<SortField>,
+    direction: Option<SortDirection>,
+    page: Option<usize>,
+    page_size: Option<usize>,
+    after: Option<String>,
+    before: Option<String>,
+    limit: Option<usize>,
+}
+
+impl SearchParams {
+    fn filter<S: Serialize + DeserializeOwned>(&self) -> Result<SearchFilter<S>, Error> {
+        let mut filter = SearchFilter::default();
+        if let Some(source) = &self.source {
+            filter = filter.source(
+                serde_json::from_str(source)
+                    .map_err(|err| Error::DeserializingField("source".into(), SerdeError(err)))?,
+            );
+        }
+        if let Some(source_prefix) = &self.source_prefix {
+            let source_prefix: Vec<String> =
+                serde_json::from_str(source_prefix).map_err(|err| {
+                    Error::DeserializingField("source_prefix".into(), SerdeError(err))
+                })?;
+            filter = filter.source_prefix(&source_prefix);
+        }
+        if let Some(levels) = &self.levels {
+            let levels = levels
+                .split(',')
+                .filter(|level| !level.is_empty())
+                .map(str::parse)
+                .collect::<Result<Vec<Level>, Error>>()?;
+            filter = filter.levels(&levels);
+        }
+        if let Some(min_level) = &self.min_level {
+            filter = filter.min_level(min_level.parse()?);
+        }
+        if let Some(content) = &self.content {
+            filter = filter.content(content);
+        }
+        if let Some(full_text) = &self.full_text {
+            filter = filter.full_text(full_text);
+        }
+        if self.highlight == Some(true) {
+            filter = filter.highlight(Highlight::default());
+        }
+        if let Some(from) = &self.from {
+            filter = filter.from(Bound::Included(parse_timestamp(from)?));
+        }
+        if let Some(to) = &self.to {
+            filter = filter.to(Bound::Excluded(parse_timestamp(to)?));
+        }
+        if let Some(trace_id) = &self.trace_id {
+            filter = filter.trace_id(trace_id);
+        }
+        if let Some(heartbeat) = self.heartbeat {
+            filter = filter.heartbeat(heartbeat);
+        }
+        if let Some(fields) = &self.fields {
+            let fields: Fields = serde_json::from_str(fields)
+                .map_err(|err| Error::DeserializingField("fields".into(), SerdeError(err)))?;
+            for (key, value) in fields {
+                filter = filter.field(key, value);
+            }
+        }
+        Ok(filter)
+    }
+
+    fn sort(&self) -> Sort {
+        Sort::new(
+            self.sort.unwrap_or_default(),
+            self.direction.unwrap_or_default(),
+        )
+    }
+
+    fn pagination(&self) -> Result<Pagination, Error> {
+        let limit = self
+            .limit
+            .or(self.page_size)
+            .unwrap_or(DEFAULT_LIMIT)
+            .min(MAX_LIMIT);
+        //An empty cursor reads from the start, or up to the end for before
+        let cursor = |cursor: &str| match cursor.is_empty() {
+            true => Ok(None),
+            false => cursor.parse().map(Some),
+        };
+        Ok(match (self.page, &self.before, &self.after) {
+            (Some(page), _, _) => Pagination::Page {
+                page,
+                page_size: limit,
+            },
+            (None, Some(before), _) => Pagination::Before {
+                cursor: cursor(before)?,
+                limit,
+            },
+            (None, None, after) => Pagination::After {
+                cursor: match after {
+                    Some(after) => cursor(after)?,
+                    None => None,
+                },
+                limit,
+            },
+        })
+    }
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<DateTime<Utc>, Error> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+        .map_err(|err| Error::ParsingTimestamp(timestamp.to_string(), ChronoParseError(err)))
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+///Error as returned to HTTP clients, problems with the request are 400s
+struct HttpError(Error);
+
+impl IntoResponse for HttpError {
+    fn into_response(self) -> Response {
+        let status = match &self.0 {
+            Error::DeserializingField(..)
+            | Error::ParsingTimestamp(..)
+            | Error::TimestampOutOfRange(_)
+            | Error::ParsingLevel(_)
+            | Error::InvalidLevel(_)
+            | Error::InvalidCursor(_)
+            | Error::InvalidPagination(_)
+            | Error::InvalidFullTextQuery(_)
+            | Error::InvalidFieldKey(_)
+            | Error::UnsupportedFilter(_) => StatusCode::BAD_REQUEST,
+            Error::WriterStopped => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        if status == StatusCode::INTERNAL_SERVER_ERROR {
+            error!("HTTP request failed: {}", self.0);
+        }
+        (
+            status,
+            Json(ErrorBody {
+                error: self.0.to_string(),
+            }),
+        )
+            .into_response()
+    }
+}
+
+impl From<Error> for HttpError {
+    fn from(value: Error) -> Self {
+        Self(value)
+    }
+}
+
+fn router<S: Serialize + DeserializeOwned + Send + Sync + 'static>(
+    manager: Arc<LogManager<S>>,
+) -> Router {
+    Router::new()
+        .route("/logs", get(search::<S>).post(ingest::<S>))
+        .route("/traces/:trace_id", get(trace::<S>))
+        .with_state(manager)
+}
+
+///Writes a batch of logs from one source in a single transaction, nothing is written unless every log is valid.
+///Responds once the batch has been committed, so a client can discard its copy.
+async fn ingest<S: Serialize + DeserializeOwned + Send + Sync + 'static>(
+    State(manager): State<Arc<LogManager<S>>>,
+    Json(batch): Json<IngestBatch<S>>,
+) -> Result<Json<IngestResponse>, HttpError> {
+    let logs = batch
+        .logs
+        .into_iter()
+        .map(|log| {
+            let mut model = manager.new_model(log.log, &batch.source)?;
+            model.ingest_id = log.id;
+            Ok(model)
+        })
+        .collect::<Result<Vec<NewLogModel>, Error>>()?;
+    let accepted = logs.len();
+    //Bypasses the write queue, which could drop the logs or fail them after responding
+    manager
+        .run_blocking(move |manager| manager.write_logs(&logs))
+        .await?;
+    Ok(Json(IngestResponse { accepted }))
+}
+
+///Runs LogManager::search, results are always paginated
+async fn search<S: Serialize + DeserializeOwned + Send + Sync + 'static>(
+    State(manager): State<Arc<LogManager<S>>>,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<SearchResults<S>>, HttpError> {
+    let filter = params.filter()?;
+    let sort = params.sort();
+    let pagination = params.pagination()?;
+    let results = manager.search_async(filter, sort, Some(pagination)).await?;
+    Ok(Json(results))
+}
+
+///Runs LogManager::get_trace
+async fn trace<S: Serialize + DeserializeOwned + Send + Sync + 'static>(
+    State(manager): State<Arc<LogManager<S>>>,
+    Path(trace_id): Path<String>,
+) -> Result<Json<SearchResults<S>>, HttpError> {
+    Ok(Json(manager.get_trace_async(trace_id).await?))
+}
+
+///Binds address up front so Builder::build can report failures
+pub(crate) async fn bind(address: SocketAddr) -> Result<TcpListener, Error> {
+    TcpListener::bind(address)
+        .await
+        .map_err(|err| Error::BindingHttpListener(address.to_string(), IoError(err)))
+}
+
+///Serves the API until the manager is stopped
+pub(crate) async fn serve<S: Serialize + DeserializeOwned + Send + Sync + 'static>(
+    manager: Arc<LogManager<S>>,
+    listener: TcpListener,
+) {
+    let address = listener.local_addr().ok();
+    info!("Log manager HTTP server listening on {address:?}");
+    let stop_manager = manager.to_owned();
+    let stopped = async move { stop_manager.stopped().await };
+    if let Err(err) = axum::serve(listener, router(manager))
+        .with_graceful_shutdown(stopped)
+        .await
+    {
+        error!("Log manager HTTP server failed: {err}");
+    }
+    info!("Log manager HTTP server stopped");
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+use crate::logs::SimpleLog;
+
+///Body of POST /logs, a batch of logs from one source
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IngestBatch<S> {
+    pub source: S,
+    pub logs: Vec<IngestLog>,
+}
+
+///The fields of SimpleLog, plus an optional idempotency key
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IngestLog {
+    #[serde(flatten)]
+    pub log: SimpleLog,
+    ///A log sent again with the same id is only stored once, lets clients retry a batch
+    ///they never got a response for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+///Response to POST /logs
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct IngestResponse {
+    ///Number of logs in the batch, including any already stored under the same id
+    pub accepted: usize,
+}
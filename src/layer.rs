@@ -0,0 +1,216 @@
+use std::{
+    cell::Cell,
+    fmt::{self, Write},
+    sync::Arc,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Event, Metadata, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+use uuid::Uuid;
+
+use crate::{
+    logs::{FieldValue, Fields, Level, SimpleLog, SpanContext},
+    manager::LogManager,
+};
+
+///Span field which sets the trace id of the span and its children instead of generating one,
+///e.g. `info_span!("request", trace_id = %incoming_trace_id)`
+pub const TRACE_ID_FIELD: &str = "trace_id";
+
+thread_local! {
+    ///Set while an event is being saved, so events the manager raises in the meantime
+    ///(e.g. serialization warnings) aren't fed back into it
+    static SAVING: Cell<bool> = const { Cell::new(false) };
+}
+
+///Clears SAVING when dropped, even if saving panics
+struct SavingGuard;
+
+impl Drop for SavingGuard {
+    fn drop(&mut self) {
+        SAVING.set(false);
+    }
+}
+
+///tracing_subscriber Layer which saves every event it sees into a LogManager.
+///Events raised inside a span are saved with its SpanContext, root spans start a new trace.
+///Events from the manager's background tasks (e.g. failed writes) are saved like any other,
+///filter out the log_manager target to leave them out.
+pub struct LogManagerLayer<S: Serialize + DeserializeOwned + Send + Sync + 'static> {
+    manager: Arc<LogManager<S>>,
+    source: Box<dyn Fn(&Metadata<'_>) -> S + Send + Sync>,
+}
+
+impl<S: Serialize + DeserializeOwned + Send + Sync + 'static> LogManagerLayer<S> {
+    ///Stores every event under the same source
+    pub fn new(manager: Arc<LogManager<S>>, source: S) -> Self
+    where
+        S: Clone,
+    {
+        Self::with_source_fn(manager, move |_| source.to_owned())
+    }
+
+    ///Picks the source for each event from its metadata (target, module path, etc)
+    pub fn with_source_fn(
+        manager: Arc<LogManager<S>>,
+        source: impl Fn(&Metadata<'_>) -> S + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            manager,
+            source: Box::new(source),
+        }
+    }
+}
+
+impl<S, Sub> Layer<Sub> for LogManagerLayer<S>
+where
+    S: Serialize + DeserializeOwned + Send + Sync + 'static,
+    Sub: Subscriber + for<'lookup> LookupSpan<'lookup>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, Sub>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = TraceIdVisitor::default();
+        attrs.record(&mut visitor);
+        let parent = span
+            .parent()
+            .and_then(|parent| parent.extensions().get::<SpanContext>().cloned());
+        let trace_id = visitor
+            .trace_id
+            .or_else(|| parent.as_ref().map(|parent| parent.trace_id.to_owned()))
+            .unwrap_or_else(|| Uuid::new_v4().simple().to_string());
+        span.extensions_mut().insert(SpanContext {
+            trace_id,
+            span_id: Some(format!("{:016x}", Uuid::new_v4().as_u64_pair().0)),
+            parent_span_id: parent.and_then(|parent| parent.span_id),
+            span_name: Some(span.name().to_string()),
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, Sub>) {
+        let mut visitor = TraceIdVisitor::default();
+        values.record(&mut visitor);
+        let (Some(trace_id), Some(span)) = (visitor.trace_id, ctx.span(id)) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(context) = extensions.get_mut::<SpanContext>() {
+            context.trace_id = trace_id;
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, Sub>) {
+        if SAVING.replace(true) {
+            return;
+        }
+        let _saving = SavingGuard;
+        let metadata = event.metadata();
+        let mut visitor = ContentVisitor::default();
+        event.record(&mut visitor);
+        let location = match (metadata.file(), metadata.line()) {
+            (Some(file), Some(line)) => format!("{file}:{line}"),
+            (Some(file), None) => file.to_string(),
+            _ => metadata.target().to_string(),
+        };
+        let source = (self.source)(metadata);
+        let (content, fields) = visitor.into_parts();
+        let mut log = SimpleLog::generate_log(Level::from(metadata.level()), location, content);
+        log.fields = fields;
+        log.span = ctx
+            .event_span(event)
+            .and_then(|span| span.extensions().get::<SpanContext>().cloned());
+        //Nowhere to report a failure to without risking recursion, the manager already warns on serialization errors
+        //Never blocks, waiting on the writer from inside the runtime it runs on could deadlock
+        let _ = self.manager.try_save_log(log, source);
+    }
+}
+
+///Picks TRACE_ID_FIELD out of the fields of a span
+#[derive(Default)]
+struct TraceIdVisitor {
+    trace_id: Option<String>,
+}
+
+impl Visit for TraceIdVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == TRACE_ID_FIELD {
+            self.trace_id = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == TRACE_ID_FIELD {
+            self.trace_id = Some(format!("{value:?}"));
+        }
+    }
+}
+
+///Formats the message of an event followed by its remaining fields as key=value pairs,
+///the remaining fields are also kept as structured fields with their original types
+#[derive(Default)]
+struct ContentVisitor {
+    message: String,
+    rendered: String,
+    fields: Fields,
+}
+
+impl ContentVisitor {
+    fn into_parts(self) -> (String, Fields) {
+        let content = if self.message.is_empty() {
+            self.rendered
+        } else if self.rendered.is_empty() {
+            self.message
+        } else {
+            format!("{} {}", self.message, self.rendered)
+        };
+        (content, self.fields)
+    }
+
+    fn record_value(&mut self, field: &Field, value: FieldValue, debug: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{debug:?}");
+            return;
+        }
+        if !self.rendered.is_empty() {
+            self.rendered.push(' ');
+        }
+        let _ = write!(self.rendered, "{}={debug:?}", field.name());
+        self.fields.insert(field.name().to_string(), value);
+    }
+}
+
+impl Visit for ContentVisitor {
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.record_value(field, value.into(), &value);
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record_value(field, value.into(), &value);
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record_value(field, value.into(), &value);
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.record_value(field, value.into(), &value);
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.record_value(field, value.into(), &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record_value(field, FieldValue::String(format!("{value:?}")), value);
+    }
+}
@@ -1,9 +1,19 @@
+pub mod aggregate;
+pub mod alert;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod database;
 pub mod error;
+pub mod heartbeat;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod ingest;
+pub mod layer;
 pub mod logs;
 pub mod manager;
+pub mod retention;
 pub mod schema;
-
-use std::sync::atomic::AtomicU32;
-
-pub static NEXT_LOG_ID: AtomicU32 = AtomicU32::new(0);
+pub mod search;
+pub mod source;
+pub mod subscription;
+pub mod writer;
@@ -1,138 +1,401 @@
-use crate::{
-    database::model::LogModel,
-    error::{Error, SerdeError},
-};
-use chrono::{TimeDelta, Utc};
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::fmt::{self, Debug};
-use tracing::metadata::Level as TracingLevel;
-
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
-pub enum Level {
-    Trace = 0,
-    Debug = 1,
-    Info = 2,
-    Warn = 3,
-    Error = 4,
-}
-
-impl From<TracingLevel> for Level {
-    fn from(value: TracingLevel) -> Self {
-        match value {
-            TracingLevel::DEBUG => Level::Debug,
-            TracingLevel::ERROR => Level::Error,
-            TracingLevel::INFO => Level::Info,
-            TracingLevel::TRACE => Level::Trace,
-            TracingLevel::WARN => Level::Warn,
-        }
-    }
-}
-
-impl From<&TracingLevel> for Level {
-    fn from(value: &TracingLevel) -> Self {
-        match *value {
-            TracingLevel::DEBUG => Level::Debug,
-            TracingLevel::ERROR => Level::Error,
-            TracingLevel::INFO => Level::Info,
-            TracingLevel::TRACE => Level::Trace,
-            TracingLevel::WARN => Level::Warn,
-        }
-    }
-}
-
-impl fmt::Display for Level {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Self::Trace => "Trace",
-                Self::Info => "Info",
-                Self::Error => "Error",
-                Self::Warn => "Warning",
-                Self::Debug => "Debug",
-            }
-        )
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Log<S> {
-    id: i32,
-    source: S,
-    ///RFC3339
-    timestamp: String,
-    level: Level,
-    location: String,
-    content: String,
-}
-
-impl<S> Log<S> {
-    pub fn into_simple_log(self) -> SimpleLog {
-        SimpleLog {
-            timestamp: self.timestamp,
-            level: self.level,
-            location: self.location,
-            content: self.content,
-        }
-    }
-}
-
-macro_rules! ok_or_return_err {
-    ($t:expr, $field_name:expr) => {
-        match $t {
-            Ok(t) => t,
-            Err(err) => {
-                let err = Error::DeserializingField($field_name.to_string(), SerdeError(err));
-                tracing::warn!("Error deserializing field {}: {err}", $field_name);
-                return Err(err);
-            }
-        }
-    };
-}
-
-impl<S: Serialize + DeserializeOwned> Log<S> {
-    pub fn from(value: LogModel) -> Result<Log<S>, Error> {
-        Ok(Self {
-            id: value.id,
-            source: ok_or_return_err!(serde_json::from_str(&value.source), "source"),
-            timestamp: ok_or_return_err!(serde_json::from_str(&value.timestamp), "timestamp"),
-            level: ok_or_return_err!(serde_json::from_str(&value.level), "level"),
-            location: ok_or_return_err!(serde_json::from_str(&value.location), "location"),
-            content: ok_or_return_err!(serde_json::from_str(&value.content), "content"),
-        })
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct SimpleLog {
-    ///RFC3339
-    pub timestamp: String,
-    pub level: Level,
-    pub location: String,
-    pub content: String,
-}
-
-impl SimpleLog {
-    pub fn generate_log_with_offset(
-        level: Level,
-        location: String,
-        content: String,
-        offset: TimeDelta,
-    ) -> Self {
-        Self {
-            timestamp: (Utc::now() + offset).to_rfc3339(),
-            level,
-            location,
-            content,
-        }
-    }
-    pub fn generate_log(level: Level, location: String, content: String) -> Self {
-        Self {
-            timestamp: Utc::now().to_rfc3339(),
-            level,
-            location,
-            content,
-        }
-    }
-}
+use crate::{
+    database::model::LogModel,
+    error::{ChronoParseError, Error, SerdeError},
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize, Serializer};
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    fmt::{self, Debug},
+    str::FromStr,
+};
+use tracing::metadata::Level as TracingLevel;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Trace = 0,
+    Debug = 1,
+    Info = 2,
+    Warn = 3,
+    Error = 4,
+}
+
+impl TryFrom<i32> for Level {
+    type Error = Error;
+
+    fn try_from(value: i32) -> Result<Self, Error> {
+        match value {
+            0 => Ok(Level::Trace),
+            1 => Ok(Level::Debug),
+            2 => Ok(Level::Info),
+            3 => Ok(Level::Warn),
+            4 => Ok(Level::Error),
+            _ => Err(Error::InvalidLevel(value)),
+        }
+    }
+}
+
+///Case insensitive, accepts the same strings as LOG_MANAGER_DISPLAY_LEVEL
+impl FromStr for Level {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        match value.to_lowercase().as_str() {
+            "trace" => Ok(Level::Trace),
+            "debug" => Ok(Level::Debug),
+            "info" => Ok(Level::Info),
+            "warn" | "warning" => Ok(Level::Warn),
+            "error" | "err" => Ok(Level::Error),
+            _ => Err(Error::ParsingLevel(value.to_string())),
+        }
+    }
+}
+
+impl From<Level> for TracingLevel {
+    fn from(value: Level) -> Self {
+        match value {
+            Level::Debug => TracingLevel::DEBUG,
+            Level::Error => TracingLevel::ERROR,
+            Level::Info => TracingLevel::INFO,
+            Level::Trace => TracingLevel::TRACE,
+            Level::Warn => TracingLevel::WARN,
+        }
+    }
+}
+
+impl From<TracingLevel> for Level {
+    fn from(value: TracingLevel) -> Self {
+        match value {
+            TracingLevel::DEBUG => Level::Debug,
+            TracingLevel::ERROR => Level::Error,
+            TracingLevel::INFO => Level::Info,
+            TracingLevel::TRACE => Level::Trace,
+            TracingLevel::WARN => Level::Warn,
+        }
+    }
+}
+
+impl From<&TracingLevel> for Level {
+    fn from(value: &TracingLevel) -> Self {
+        match *value {
+            TracingLevel::DEBUG => Level::Debug,
+            TracingLevel::ERROR => Level::Error,
+            TracingLevel::INFO => Level::Info,
+            TracingLevel::TRACE => Level::Trace,
+            TracingLevel::WARN => Level::Warn,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Trace => "Trace",
+                Self::Info => "Info",
+                Self::Error => "Error",
+                Self::Warn => "Warning",
+                Self::Debug => "Debug",
+            }
+        )
+    }
+}
+
+///Value of a structured field, stored as the JSON type it maps to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    ///NaN and infinities have no JSON representation, they are stored as a String ("NaN", "inf", "-inf")
+    #[serde(serialize_with = "serialize_f64")]
+    F64(f64),
+    String(String),
+}
+
+fn serialize_f64<Ser: Serializer>(value: &f64, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+    match value.is_finite() {
+        true => serializer.serialize_f64(*value),
+        false => serializer.serialize_str(&value.to_string()),
+    }
+}
+
+impl FieldValue {
+    ///Orders values of the same kind the way SQLite does, numbers compare regardless of representation.
+    ///None for values of different kinds, e.g. a string and a number.
+    pub(crate) fn compare(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Bool(a), Self::Bool(b)) => Some(a.cmp(b)),
+            (Self::String(a), Self::String(b)) => Some(a.cmp(b)),
+            (Self::I64(a), Self::I64(b)) => Some(a.cmp(b)),
+            (Self::U64(a), Self::U64(b)) => Some(a.cmp(b)),
+            (Self::I64(a), Self::U64(b)) => Some((*a as i128).cmp(&(*b as i128))),
+            (Self::U64(a), Self::I64(b)) => Some((*a as i128).cmp(&(*b as i128))),
+            (a, b) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::I64(value) => Some(*value as f64),
+            Self::U64(value) => Some(*value as f64),
+            Self::F64(value) => Some(*value),
+            Self::Bool(_) | Self::String(_) => None,
+        }
+    }
+}
+
+impl From<bool> for FieldValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<i32> for FieldValue {
+    fn from(value: i32) -> Self {
+        Self::I64(value as i64)
+    }
+}
+
+impl From<i64> for FieldValue {
+    fn from(value: i64) -> Self {
+        Self::I64(value)
+    }
+}
+
+impl From<u32> for FieldValue {
+    fn from(value: u32) -> Self {
+        Self::U64(value as u64)
+    }
+}
+
+impl From<u64> for FieldValue {
+    fn from(value: u64) -> Self {
+        Self::U64(value)
+    }
+}
+
+impl From<f64> for FieldValue {
+    ///Non-finite values become the String they are stored as
+    fn from(value: f64) -> Self {
+        match value.is_finite() {
+            true => Self::F64(value),
+            false => Self::String(value.to_string()),
+        }
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+///Structured key-value data of a log, e.g. request ids and durations
+pub type Fields = BTreeMap<String, FieldValue>;
+
+///Span a log was raised in, filled in by LogManagerLayer or set by the caller
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SpanContext {
+    ///Shared by every log of one request, propagate it to follow the request across sources
+    pub trace_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_span_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span_name: Option<String>,
+}
+
+impl SpanContext {
+    pub fn new(trace_id: impl Into<String>) -> Self {
+        Self {
+            trace_id: trace_id.into(),
+            span_id: None,
+            parent_span_id: None,
+            span_name: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Log<S> {
+    id: i32,
+    source: S,
+    ///RFC3339
+    timestamp: String,
+    level: Level,
+    location: String,
+    content: String,
+    #[serde(default, skip_serializing_if = "Fields::is_empty")]
+    fields: Fields,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    span: Option<SpanContext>,
+}
+
+impl<S> Log<S> {
+    ///Row id, increases in insertion order
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    ///RFC3339
+    pub fn timestamp(&self) -> &str {
+        &self.timestamp
+    }
+
+    pub fn into_simple_log(self) -> SimpleLog {
+        SimpleLog {
+            timestamp: self.timestamp,
+            level: self.level,
+            location: self.location,
+            content: self.content,
+            fields: self.fields,
+            span: self.span,
+        }
+    }
+}
+
+macro_rules! ok_or_return_err {
+    ($t:expr, $field_name:expr) => {
+        match $t {
+            Ok(t) => t,
+            Err(err) => {
+                let err = Error::DeserializingField($field_name.to_string(), SerdeError(err));
+                tracing::warn!("Error deserializing field {}: {err}", $field_name);
+                return Err(err);
+            }
+        }
+    };
+}
+
+impl<S: Serialize + DeserializeOwned> Log<S> {
+    pub fn from(value: LogModel) -> Result<Log<S>, Error> {
+        Self::decode(value).map_err(|(err, _)| err)
+    }
+
+    ///Same as from, handing the model back when it can't be decoded
+    pub(crate) fn decode(value: LogModel) -> Result<Log<S>, (Error, Box<LogModel>)> {
+        let (source, timestamp, level, fields) = match Self::decode_columns(&value) {
+            Ok(columns) => columns,
+            Err(err) => return Err((err, Box::new(value))),
+        };
+        Ok(Self {
+            id: value.id,
+            source,
+            timestamp,
+            level,
+            location: value.location,
+            content: value.content,
+            fields,
+            span: value.trace_id.map(|trace_id| SpanContext {
+                trace_id,
+                span_id: value.span_id,
+                parent_span_id: value.parent_span_id,
+                span_name: value.span_name,
+            }),
+        })
+    }
+
+    fn decode_columns(value: &LogModel) -> Result<(S, String, Level, Fields), Error> {
+        Ok((
+            ok_or_return_err!(serde_json::from_str(&value.source), "source"),
+            micros_to_timestamp(value.timestamp)?,
+            Level::try_from(value.level)?,
+            match &value.fields {
+                Some(fields) => ok_or_return_err!(serde_json::from_str(fields), "fields"),
+                None => Fields::new(),
+            },
+        ))
+    }
+}
+
+///Parses an RFC3339 timestamp into microseconds since the unix epoch, which is how it is stored
+pub(crate) fn timestamp_to_micros(timestamp: &str) -> Result<i64, Error> {
+    match DateTime::parse_from_rfc3339(timestamp) {
+        Ok(timestamp) => Ok(timestamp.timestamp_micros()),
+        Err(err) => {
+            let err = Error::ParsingTimestamp(timestamp.to_string(), ChronoParseError(err));
+            tracing::warn!("{err}");
+            Err(err)
+        }
+    }
+}
+
+///Stored timestamps come back as UTC RFC3339 with microsecond precision
+pub(crate) fn micros_to_timestamp(micros: i64) -> Result<String, Error> {
+    match DateTime::<Utc>::from_timestamp_micros(micros) {
+        Some(timestamp) => Ok(timestamp.to_rfc3339()),
+        None => {
+            let err = Error::TimestampOutOfRange(micros);
+            tracing::warn!("{err}");
+            Err(err)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SimpleLog {
+    ///RFC3339
+    pub timestamp: String,
+    pub level: Level,
+    pub location: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Fields::is_empty")]
+    pub fields: Fields,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<SpanContext>,
+}
+
+impl SimpleLog {
+    pub fn generate_log_with_offset(
+        level: Level,
+        location: String,
+        content: String,
+        offset: TimeDelta,
+    ) -> Self {
+        Self {
+            timestamp: (Utc::now() + offset).to_rfc3339(),
+            level,
+            location,
+            content,
+            fields: Fields::new(),
+            span: None,
+        }
+    }
+    pub fn generate_log(level: Level, location: String, content: String) -> Self {
+        Self {
+            timestamp: Utc::now().to_rfc3339(),
+            level,
+            location,
+            content,
+            fields: Fields::new(),
+            span: None,
+        }
+    }
+
+    ///Adds a structured field, replacing any previous value of key
+    pub fn field(mut self, key: impl Into<String>, value: impl Into<FieldValue>) -> Self {
+        self.fields.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn span(mut self, span: SpanContext) -> Self {
+        self.span = Some(span);
+        self
+    }
+}
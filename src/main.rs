@@ -2,6 +2,7 @@ use log_manager::{
     error::Error,
     logs::{Level, SimpleLog},
     manager::Pagination,
+    search::{SearchFilter, SearchResults, Sort},
 };
 use serde::{Deserialize, Serialize};
 use std::{
@@ -34,24 +35,14 @@ async fn main() -> Result<(), Error> {
 
     let level_filter = LevelFilter::from_level({
         let env_display_level = match std::env::var("LOG_MANAGER_DISPLAY_LEVEL") {
-            Ok(level_str) => match level_str.to_lowercase().as_str() {
-                "trace" => Some(tracing::Level::TRACE),
-                "debug" => Some(tracing::Level::DEBUG),
-                "info" => Some(tracing::Level::INFO),
-                "warn" | "warning" => Some(tracing::Level::WARN),
-                "error" | "err" => Some(tracing::Level::ERROR),
-                _ => None,
-            },
+            Ok(level_str) => level_str.parse::<Level>().ok().map(tracing::Level::from),
             Err(_) => None,
         };
         if env_display_level.is_none() {
             prestart_logs.push("ENV \"LOG_MANAGER_DISPLAY_LEVEL\" not set".to_string());
         }
         let display_level = env_display_level.unwrap_or(tracing::Level::INFO);
-        prestart_logs.push(format!(
-            "Running with display level: {}",
-            display_level.to_string(),
-        ));
+        prestart_logs.push(format!("Running with display level: {display_level}"));
         display_level
     });
 
@@ -111,19 +102,33 @@ async fn main() -> Result<(), Error> {
             LogSource::Agent(uuid!("f068c603-b2d8-4aab-a06b-478dea93bcea")),
         )?;
     }
-    let (total_count, results) = log_manager.search(None, None, "".into(), &[Level::Debug])?;
+    log_manager.flush().await?;
+    let SearchResults {
+        total_count,
+        logs: results,
+        ..
+    } = log_manager.search(
+        &SearchFilter::default().levels(&[Level::Debug]),
+        Sort::default(),
+        None,
+    )?;
     for i in 1..(total_count / 10) {
         let now = Instant::now();
-        let (total_count, results) = log_manager.search(
-            Some(LogSource::Agent(uuid!(
-                "f068c603-b2d8-4aab-a06b-478dea93bcea"
-            ))),
+        let SearchResults {
+            total_count,
+            logs: results,
+            ..
+        } = log_manager.search(
+            &SearchFilter::default()
+                .source(LogSource::Agent(uuid!(
+                    "f068c603-b2d8-4aab-a06b-478dea93bcea"
+                )))
+                .levels(&[Level::Debug]),
+            Sort::default(),
             Some(Pagination::Page {
                 page: i as usize,
                 page_size: 2,
             }),
-            "".into(),
-            &[Level::Debug],
         )?;
         debug!("Total before pagination: {total_count}");
         debug!("{}ns", now.elapsed().as_nanos());
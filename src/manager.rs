@@ -1,29 +1,43 @@
+#[cfg(feature = "http")]
+use std::net::SocketAddr;
 use std::{
+    any::{type_name, TypeId},
+    collections::{HashMap, HashSet},
     marker::PhantomData,
+    pin::pin,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
+    time::Duration,
 };
 
-use diesel::{
-    dsl::{count_star, max},
-    ExpressionMethods, QueryDsl, RunQueryDsl, SqliteConnection, TextExpressionMethods,
-};
+use chrono::Utc;
+use diesel::{dsl::count_star, QueryDsl, RunQueryDsl};
 use parking_lot::Mutex;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use tokio::sync::Notify;
+use tokio::sync::{broadcast, mpsc, Notify};
 use tracing::{error, info, warn};
 
 use crate::{
-    database::{establish_connection, model::LogModel, run_migrations, MIGRATIONS},
-    error::{BuilderError, DieselResultError, Error, SerdeError},
-    logs::{Level, Log, SimpleLog},
-    schema::log::{
-        self as log_table,
-        dsl::{content as content_db, level as level_db, log as log_data, source as source_db},
+    aggregate::{AggregateRow, GroupBy, SourceSummary},
+    alert::{AlertEngine, AlertRule, FiredAlert, Rule},
+    database::{
+        model::{LogModel, NewLogModel},
+        pool::ConnectionPool,
+        run_migrations, BusyRetry, ConnectionOptions, MIGRATIONS,
+    },
+    error::{BuilderError, Error},
+    heartbeat::{Heartbeat, HeartbeatMonitor},
+    logs::{Log, SimpleLog},
+    retention::RetentionPolicy,
+    search::{
+        load_snippets, Cursor, FailedLog, SearchFilter, SearchResults, Sort, SortDirection,
+        SortField,
     },
-    serialize_or_return_err, NEXT_LOG_ID,
+    source::{backfill_paths, stored_path, SourcePath},
+    subscription::Subscription,
+    writer::{write_batch, BackpressurePolicy, WriteQueue},
 };
 
 #[derive(Debug)]
@@ -39,6 +53,22 @@ pub struct Builder {
     stop: Option<Arc<AtomicBool>>,
     stop_notify: Option<Arc<Notify>>,
     //defaulted
+    reader_connections: usize,
+    batch_size: usize,
+    flush_interval: Duration,
+    queue_capacity: usize,
+    backpressure_policy: BackpressurePolicy,
+    retention: Option<RetentionPolicy>,
+    retention_interval: Duration,
+    connection_options: ConnectionOptions,
+    subscription_capacity: usize,
+    heartbeat: Option<Heartbeat>,
+    alert_rules: Vec<Rule>,
+    source_path: Option<SourcePath>,
+    ///Source type each hook above was set up with, build fails unless they are all its S
+    hook_sources: Vec<(TypeId, &'static str)>,
+    #[cfg(feature = "http")]
+    http_address: Option<SocketAddr>,
 }
 
 impl Default for Builder {
@@ -47,6 +77,21 @@ impl Default for Builder {
             database_url: None,
             stop: None,
             stop_notify: None,
+            reader_connections: 4,
+            batch_size: 256,
+            flush_interval: Duration::from_millis(100),
+            queue_capacity: 8192,
+            backpressure_policy: BackpressurePolicy::Block,
+            retention: None,
+            retention_interval: Duration::from_secs(60),
+            connection_options: ConnectionOptions::default(),
+            subscription_capacity: 1024,
+            heartbeat: None,
+            alert_rules: Vec::new(),
+            source_path: None,
+            hook_sources: Vec::new(),
+            #[cfg(feature = "http")]
+            http_address: None,
         }
     }
 }
@@ -67,7 +112,125 @@ impl Builder {
         self
     }
 
-    pub async fn build<S: Serialize + DeserializeOwned>(self) -> Result<Arc<LogManager<S>>, Error> {
+    ///Number of pooled read connections, searches fall back to the writer connection when set to 0
+    pub fn reader_connections(mut self, reader_connections: usize) -> Self {
+        self.reader_connections = reader_connections;
+        self
+    }
+
+    ///Maximum number of logs committed in a single transaction by the writer task
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    ///How long the writer task waits for a batch to fill before committing what it has
+    pub fn flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    ///Maximum number of logs waiting to be written before the backpressure policy kicks in
+    pub fn queue_capacity(mut self, queue_capacity: usize) -> Self {
+        self.queue_capacity = queue_capacity;
+        self
+    }
+
+    pub fn backpressure_policy(mut self, backpressure_policy: BackpressurePolicy) -> Self {
+        self.backpressure_policy = backpressure_policy;
+        self
+    }
+
+    ///Logs are kept forever unless a retention policy is set
+    pub fn retention(mut self, retention: RetentionPolicy) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    ///How often the retention policy is enforced
+    pub fn retention_interval(mut self, retention_interval: Duration) -> Self {
+        self.retention_interval = retention_interval;
+        self
+    }
+
+    ///Enables write-ahead logging, on by default
+    pub fn wal(mut self, wal: bool) -> Self {
+        self.connection_options.wal = wal;
+        self
+    }
+
+    ///How long a connection waits on locks held by other connections or processes, 5 seconds by default
+    pub fn busy_timeout(mut self, busy_timeout: Duration) -> Self {
+        self.connection_options.busy_timeout = busy_timeout;
+        self
+    }
+
+    ///How writes are retried when the database is still locked after the busy timeout
+    pub fn busy_retry(mut self, busy_retry: BusyRetry) -> Self {
+        self.connection_options.busy_retry = busy_retry;
+        self
+    }
+
+    ///Number of written logs buffered for subscribers, a subscriber which falls further behind
+    ///than this gets Error::SubscriptionLagged
+    pub fn subscription_capacity(mut self, subscription_capacity: usize) -> Self {
+        self.subscription_capacity = subscription_capacity;
+        self
+    }
+
+    ///Watches for sources which stop logging.
+    ///S has to be the type later passed to build, which otherwise returns BuilderError::SourceTypeMismatch.
+    pub fn heartbeat<S: Serialize + DeserializeOwned + 'static>(
+        mut self,
+        heartbeat: HeartbeatMonitor<S>,
+    ) -> Self {
+        self.heartbeat = Some(heartbeat.into_heartbeat());
+        self.hook_source::<S>();
+        self
+    }
+
+    ///Evaluates the rule against every written log.
+    ///S has to be the type later passed to build, which otherwise returns BuilderError::SourceTypeMismatch.
+    pub fn alert_rule<S: Serialize + DeserializeOwned + 'static>(
+        mut self,
+        alert_rule: AlertRule<S>,
+    ) -> Self {
+        self.alert_rules.push(alert_rule.into_rule());
+        self.hook_source::<S>();
+        self
+    }
+
+    ///Overrides source::Source::source_path, the components SearchFilter::source_prefix matches sources by.
+    ///S has to be the type later passed to build, which otherwise returns BuilderError::SourceTypeMismatch.
+    pub fn source_path<S: DeserializeOwned + 'static>(
+        mut self,
+        source_path: impl Fn(&S) -> Vec<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.source_path = Some(Box::new(move |source| {
+            serde_json::from_str::<S>(source)
+                .ok()
+                .map(|source| source_path(&source))
+        }));
+        self.hook_source::<S>();
+        self
+    }
+
+    ///Records the source type a hook was set up with, it is stored as JSON until build checks it
+    fn hook_source<S: 'static>(&mut self) {
+        self.hook_sources
+            .push((TypeId::of::<S>(), type_name::<S>()));
+    }
+
+    ///Serves the JSON API from the http module on address, nothing listens unless this is set
+    #[cfg(feature = "http")]
+    pub fn http_address(mut self, http_address: SocketAddr) -> Self {
+        self.http_address = Some(http_address);
+        self
+    }
+
+    pub async fn build<S: Serialize + DeserializeOwned + Send + Sync + 'static>(
+        mut self,
+    ) -> Result<Arc<LogManager<S>>, Error> {
         let mut missing_properties: Vec<RequiredProperties> = Vec::new();
         if self.database_url.is_none() {
             missing_properties.push(RequiredProperties::DatabaseUrl);
@@ -78,171 +241,675 @@ impl Builder {
                 missing_properties
             ))));
         }
+        if let Some((_, hook_source)) = self
+            .hook_sources
+            .iter()
+            .find(|(type_id, _)| *type_id != TypeId::of::<S>())
+        {
+            return Err(Error::Builder(BuilderError::SourceTypeMismatch(format!(
+                "{hook_source} instead of {}",
+                type_name::<S>()
+            ))));
+        }
 
-        let stop: Arc<AtomicBool> = self.stop.unwrap_or(Arc::new(AtomicBool::new(false)));
-        let stop_notify: Arc<Notify> = self.stop_notify.unwrap_or(Arc::new(Notify::new()));
+        let stop: Arc<AtomicBool> = self.stop.take().unwrap_or(Arc::new(AtomicBool::new(false)));
+        let stop_notify: Arc<Notify> = self.stop_notify.take().unwrap_or(Arc::new(Notify::new()));
+        let database_url: String = self.database_url.take().unwrap();
 
         let log_manager: Arc<LogManager<S>> =
-            LogManager::<S>::new(stop, stop_notify, self.database_url.unwrap()).await?;
+            LogManager::<S>::new(stop, stop_notify, database_url, self).await?;
 
         Ok(log_manager)
     }
 }
 
-fn get_next_log_id(database_url: &str) -> Result<u32, Error> {
-    let mut connection = establish_connection(database_url)?;
-    let max_id: i32 = match log_table::table
-        .select(max(log_table::id))
-        .first::<Option<i32>>(&mut connection)
-    {
-        Ok(max_id) => max_id.unwrap_or(0),
-        Err(err) => {
-            let err = Error::DieselResult(DieselResultError(err));
-            error!("{err}");
-            return Err(err);
-        }
-    };
-
-    if max_id.is_negative() {
-        let err = Error::NegativeLogID(max_id);
-        error!("{err}");
-        return Err(err);
-    }
-
-    Ok(max_id as u32)
-}
-
 #[derive(Serialize, Deserialize, Clone, Copy)]
 pub enum Pagination {
+    ///Pages start at 1
     Page { page: usize, page_size: usize },
+    ///Up to limit logs following the cursor in sort order, or from the start when there is no cursor
+    After {
+        cursor: Option<Cursor>,
+        limit: usize,
+    },
+    ///Up to limit logs preceding the cursor in sort order, or up to the end when there is no cursor
+    Before {
+        cursor: Option<Cursor>,
+        limit: usize,
+    },
+}
+
+///Limit of a page as SQLite takes it, which would read a negative limit as no limit at all
+fn sql_limit(limit: usize) -> Result<i64, Error> {
+    i64::try_from(limit).map_err(|_| Error::InvalidPagination(format!("limit {limit}")))
+}
+
+///Database failures are logged, mistakes in the filter are left to the caller to report
+fn log_query_error(err: &Error) {
+    if let Error::DieselResult(_) = err {
+        error!("{err}");
+    }
 }
 
 pub struct LogManager<S: Serialize + DeserializeOwned> {
     stop: Arc<AtomicBool>,
     stop_notify: Arc<Notify>,
-    database_url: String,
-    internal_lock: Arc<Mutex<()>>,
+    connection_pool: ConnectionPool,
+    write_queue: WriteQueue,
+    batch_size: usize,
+    flush_interval: Duration,
+    retention: Option<RetentionPolicy>,
+    retention_interval: Duration,
+    live_sender: Mutex<Option<broadcast::Sender<Arc<LogModel>>>>,
+    heartbeat: Option<Heartbeat>,
+    alerts: Option<AlertEngine>,
+    source_path: Option<SourcePath>,
+    #[cfg(feature = "http")]
+    http_address: Option<SocketAddr>,
     _phantom: PhantomData<S>,
 }
-impl<S: Serialize + DeserializeOwned> LogManager<S> {
+impl<S: Serialize + DeserializeOwned + Send + Sync + 'static> LogManager<S> {
     //TODO: add an option on the builder which configures whether this server should stop with ctrl+c or wait for the stop signal
     async fn new(
         stop: Arc<AtomicBool>,
         stop_notify: Arc<Notify>,
         database_url: String,
+        options: Builder,
     ) -> Result<Arc<Self>, Error> {
+        let connection_pool = ConnectionPool::new(
+            &database_url,
+            options.reader_connections,
+            &options.connection_options,
+        )?;
         info!("Running log manager database migrations");
         {
-            let mut connection: SqliteConnection = establish_connection(&database_url)?;
-            match run_migrations(&mut connection, MIGRATIONS) {
+            let mut connection = connection_pool.writer();
+            //Holding the write lock for the whole run keeps processes starting at the same time
+            //from applying the same migration twice
+            match connection
+                .immediate_transaction(|connection| run_migrations(connection, MIGRATIONS))
+            {
                 Ok(_) => info!("Log manager database migrations ran succesfully"),
                 Err(err) => return Err(Error::RunningMigrations(err.to_string())),
             }
+            let backfilled = options.connection_options.busy_retry.run(|| {
+                backfill_paths(&mut connection, |source| {
+                    stored_path(source, options.source_path.as_ref())
+                })
+            })?;
+            if backfilled > 0 {
+                info!("Stored the source path of {backfilled} existing logs");
+            }
         }
-        NEXT_LOG_ID.store(get_next_log_id(&database_url)? + 1, Ordering::SeqCst);
         let manager = Arc::new(Self {
             stop,
             stop_notify,
-            database_url,
-            internal_lock: Arc::new(Mutex::new(())),
+            connection_pool,
+            write_queue: WriteQueue::new(options.queue_capacity, options.backpressure_policy),
+            batch_size: options.batch_size,
+            flush_interval: options.flush_interval,
+            retention: options.retention,
+            retention_interval: options.retention_interval,
+            live_sender: Mutex::new(Some(
+                broadcast::channel(options.subscription_capacity.max(1)).0,
+            )),
+            heartbeat: options.heartbeat,
+            source_path: options.source_path,
+            alerts: match options.alert_rules.is_empty() {
+                true => None,
+                false => Some(AlertEngine::new(options.alert_rules)),
+            },
+            #[cfg(feature = "http")]
+            http_address: options.http_address,
             _phantom: PhantomData,
         });
-        Self::start_server(manager.to_owned()).await;
+        Self::start_server(manager.to_owned()).await?;
         Ok(manager)
     }
-    async fn start_server(_manager: Arc<Self>) {
-        //task thread disabled until there is actually a need
-        /* tokio::task::spawn(async move {
-            //
-        }); */
+    async fn start_server(manager: Arc<Self>) -> Result<(), Error> {
+        #[cfg(feature = "http")]
+        if let Some(http_address) = manager.http_address {
+            let listener = crate::http::bind(http_address).await?;
+            tokio::task::spawn(crate::http::serve(manager.to_owned(), listener));
+        }
+        if manager.retention.is_some() {
+            tokio::task::spawn(Self::run_retention(manager.to_owned()));
+        }
+        if manager.heartbeat.is_some() {
+            tokio::task::spawn(Self::run_heartbeat(manager.to_owned()));
+        }
+        if let Some(receiver) = manager.alerts.as_ref().and_then(AlertEngine::take_receiver) {
+            tokio::task::spawn(Self::run_alerts(manager.to_owned(), receiver));
+        }
+        tokio::task::spawn(Self::run_writer(manager));
+        Ok(())
     }
 
-    pub fn save_log(&self, log: SimpleLog, source: S) -> Result<usize, Error> {
-        let _guard = self.internal_lock.lock();
-        let sqlite_connection = &mut establish_connection(&self.database_url)?;
-        let log = LogModel::from(log, source)?;
-        let insert_into = diesel::insert_into(log_table::table);
-        match insert_into.values(log).execute(sqlite_connection) {
-            Ok(rows_affected) => Ok(rows_affected),
-            Err(err) => Err(Error::DieselResult(DieselResultError(err))),
-        }
+    ///Encoded path of a source as stored, None when the Builder::source_path override can't decode it
+    fn stored_source_path(&self, source: &str) -> Option<String> {
+        stored_path(source, self.source_path.as_ref())
     }
-    pub fn search(
+
+    ///NewLogModel::from with the source path filled in
+    pub(crate) fn new_model(
         &self,
-        source: Option<S>,
-        pagination: Option<Pagination>,
-        content_search: Option<&str>,
-        levels: &[Level],
-    ) -> Result<(i64, Vec<Log<S>>), Error> {
-        let levels = {
-            let mut levels_: Vec<String> = Vec::new();
-            for level in levels.iter() {
-                match serde_json::to_string(level) {
-                    Ok(level_serialized) => levels_.push(level_serialized),
-                    Err(err) => {
-                        let err = Error::SerializingField("level".into(), SerdeError(err));
-                        warn!("{err}");
-                        return Err(err);
+        log: SimpleLog,
+        source: impl Serialize,
+    ) -> Result<NewLogModel, Error> {
+        let mut model = NewLogModel::from(log, source)?;
+        model.source_path = self.stored_source_path(&model.source);
+        Ok(model)
+    }
+
+    ///Resolves once LogManager::stop has been called
+    pub(crate) async fn stopped(&self) {
+        let mut stop_notified = pin!(self.stop_notify.notified());
+        stop_notified.as_mut().enable();
+        if !self.stop.load(Ordering::SeqCst) {
+            stop_notified.await;
+        }
+    }
+
+    async fn run_retention(manager: Arc<Self>) {
+        while !manager.stop.load(Ordering::SeqCst) {
+            let manager_ = manager.to_owned();
+            let result = tokio::task::spawn_blocking(move || match &manager_.retention {
+                Some(retention) => {
+                    let connection_pool = &manager_.connection_pool;
+                    connection_pool
+                        .busy_retry()
+                        .run(|| retention.prune(connection_pool))
+                }
+                None => Ok(0),
+            })
+            .await;
+            match result {
+                Ok(Ok(0)) => {}
+                Ok(Ok(removed)) => info!("Retention policy removed {removed} logs"),
+                Ok(Err(err)) => error!("Failed to enforce retention policy: {err}"),
+                Err(err) => error!("Retention task panicked: {err}"),
+            }
+            tokio::select! {
+                _ = manager.stopped() => {},
+                _ = tokio::time::sleep(manager.retention_interval) => {},
+            }
+        }
+        info!("Log manager retention task stopped");
+    }
+
+    async fn run_heartbeat(manager: Arc<Self>) {
+        let Some(check_interval) = manager
+            .heartbeat
+            .as_ref()
+            .map(|heartbeat| heartbeat.check_interval)
+        else {
+            return;
+        };
+        let started = Utc::now().timestamp_micros();
+        let mut silent = HashSet::new();
+        while !manager.stop.load(Ordering::SeqCst) {
+            let manager_ = manager.to_owned();
+            let result = tokio::task::spawn_blocking(move || match &manager_.heartbeat {
+                Some(heartbeat) => heartbeat.last_seen(&mut manager_.connection_pool.reader()),
+                None => Ok(HashMap::new()),
+            })
+            .await;
+            match result {
+                Ok(Ok(last_seen)) => {
+                    if let Some(heartbeat) = &manager.heartbeat {
+                        let mut logs = heartbeat.check(&last_seen, started, &mut silent);
+                        for log in &mut logs {
+                            log.source_path = manager.stored_source_path(&log.source);
+                        }
+                        //Queueing can block under BackpressurePolicy::Block
+                        let queued = manager
+                            .run_blocking(move |manager| {
+                                logs.into_iter().try_for_each(|log| manager.queue_log(log))
+                            })
+                            .await;
+                        if let Err(err) = queued {
+                            error!("Failed to queue heartbeat log: {err}");
+                        }
                     }
                 }
+                Ok(Err(err)) => error!("Failed to check heartbeats: {err}"),
+                Err(err) => error!("Heartbeat task panicked: {err}"),
+            }
+            tokio::select! {
+                _ = manager.stopped() => {},
+                _ = tokio::time::sleep(check_interval) => {},
             }
-            levels_
+        }
+        info!("Log manager heartbeat task stopped");
+    }
+
+    async fn run_alerts(manager: Arc<Self>, mut receiver: mpsc::UnboundedReceiver<FiredAlert>) {
+        let Some(alerts) = manager.alerts.as_ref() else {
+            return;
         };
-        let mut sqlite_connection = establish_connection(&self.database_url)?;
-        let mut query = log_data.into_boxed();
-        let mut count_query = log_data.into_boxed();
-        if let Some(source) = source {
-            let source_serialized = serialize_or_return_err!(&source, "source");
-            query = query.filter(source_db.eq(source_serialized.to_owned()));
-            count_query = count_query.filter(source_db.eq(source_serialized));
-        }
-        if !levels.is_empty() {
-            query = query.filter(level_db.eq_any(levels.iter()));
-            count_query = count_query.filter(level_db.eq_any(levels.iter()));
-        }
-        if let Some(content_search) = content_search {
-            query = query.filter(content_db.like(format!("%{content_search}%")));
-            count_query = count_query.filter(content_db.like(format!("%{content_search}%")));
+        loop {
+            tokio::select! {
+                _ = manager.stopped() => break,
+                fired = receiver.recv() => match fired {
+                    Some((index, alert)) => alerts.dispatch(index, alert).await,
+                    None => break,
+                },
+            }
+        }
+        info!("Log manager alert task stopped");
+    }
+
+    async fn run_writer(manager: Arc<Self>) {
+        loop {
+            let batch = manager
+                .write_queue
+                .next_batch(
+                    manager.batch_size,
+                    manager.flush_interval,
+                    &manager.stop,
+                    &manager.stop_notify,
+                )
+                .await;
+            let (Some((first_sequence, _)), Some((last_sequence, _))) =
+                (batch.first(), batch.last())
+            else {
+                break;
+            };
+            let (first_sequence, last_sequence) = (*first_sequence, *last_sequence);
+            let logs: Vec<NewLogModel> = batch.into_iter().map(|(_, log)| log).collect();
+            let manager_ = manager.to_owned();
+            let result = tokio::task::spawn_blocking(move || {
+                let connection_pool = &manager_.connection_pool;
+                connection_pool
+                    .busy_retry()
+                    .run(|| write_batch(&mut connection_pool.writer(), &logs))
+            })
+            .await;
+            match result {
+                Ok(Ok(written)) => manager.publish(written),
+                Ok(Err(err)) => {
+                    error!("Failed to write batch of logs: {err}");
+                    manager
+                        .write_queue
+                        .mark_failed(first_sequence, last_sequence);
+                }
+                Err(err) => {
+                    error!("Log writer panicked: {err}");
+                    manager
+                        .write_queue
+                        .mark_failed(first_sequence, last_sequence);
+                }
+            }
+            manager.write_queue.mark_committed(last_sequence);
+        }
+        manager.write_queue.close();
+        //Dropping the sender closes every subscription
+        manager.live_sender.lock().take();
+        info!("Log manager writer stopped");
+    }
+
+    ///Hands logs which were just committed to the alert rules and subscriptions
+    fn publish(&self, written: Vec<LogModel>) {
+        if let Some(alerts) = &self.alerts {
+            written.iter().for_each(|log| alerts.evaluate(log));
+        }
+        if let Some(live_sender) = self.live_sender.lock().as_ref() {
+            if live_sender.receiver_count() == 0 {
+                return;
+            }
+            for log in written {
+                //Only fails when every receiver has been dropped in the meantime
+                let _ = live_sender.send(Arc::new(log));
+            }
+        }
+    }
+
+    ///Streams every log matching filter which is written from now on.
+    ///Full-text filters are evaluated by SQLite and can't be used for subscriptions.
+    pub fn subscribe(&self, filter: &SearchFilter<S>) -> Result<Subscription<S>, Error> {
+        let matcher = filter.matcher()?;
+        match self.live_sender.lock().as_ref() {
+            Some(live_sender) => Ok(Subscription::new(live_sender.subscribe(), matcher)),
+            None => Err(Error::WriterStopped),
         }
+    }
+
+    ///Queues a log to be written by the background writer task, see Builder::backpressure_policy
+    ///for what happens when the queue is full.
+    ///Under BackpressurePolicy::Block this blocks the calling thread, from async code use save_log_async.
+    pub fn save_log(&self, log: SimpleLog, source: S) -> Result<(), Error> {
+        self.queue_log(self.new_model(log, source)?)
+    }
+
+    ///Writes logs in a single transaction on the calling thread, bypassing the write queue,
+    ///so they can be committed before logs queued earlier with save_log.
+    ///Returns the id of each log in the order given, or why it couldn't be serialized,
+    ///failed logs are skipped and the rest are still written.
+    ///Err if the transaction failed, or if the number of logs written doesn't match and ids can't be assigned.
+    pub fn save_logs(
+        &self,
+        logs: impl IntoIterator<Item = (SimpleLog, S)>,
+    ) -> Result<Vec<Result<i32, Error>>, Error> {
+        let mut results = Vec::new();
+        let mut models = Vec::new();
+        for (log, source) in logs {
+            match self.new_model(log, source) {
+                Ok(model) => {
+                    models.push(model);
+                    //Replaced by the id once written
+                    results.push(Ok(0));
+                }
+                Err(err) => results.push(Err(err)),
+            }
+        }
+        if models.is_empty() {
+            return Ok(results);
+        }
+        //Nothing is skipped without an ingest_id, so the ids line up with the successful results
+        let ids = self.write_logs(&models)?;
+        if ids.len() != models.len() {
+            let err = Error::WrittenCountMismatch(models.len(), ids.len());
+            error!("{err}");
+            return Err(err);
+        }
+        for (id, written_id) in results.iter_mut().flatten().zip(ids) {
+            *id = written_id;
+        }
+        Ok(results)
+    }
+
+    ///Writes models in a single transaction on the calling thread and publishes them,
+    ///returning the ids of the logs which were written
+    pub(crate) fn write_logs(&self, models: &[NewLogModel]) -> Result<Vec<i32>, Error> {
+        if self.stop.load(Ordering::SeqCst) {
+            return Err(Error::WriterStopped);
+        }
+        let written = self
+            .connection_pool
+            .busy_retry()
+            .run(|| write_batch(&mut self.connection_pool.writer(), models))
+            .inspect_err(|err| error!("Failed to write batch of logs: {err}"))?;
+        let ids = written.iter().map(|log| log.id).collect();
+        self.publish(written);
+        Ok(ids)
+    }
+
+    ///Logs queued before stop are still written, anything after is rejected
+    pub(crate) fn queue_log(&self, log: NewLogModel) -> Result<(), Error> {
+        if self.stop.load(Ordering::SeqCst) {
+            return Err(Error::WriterStopped);
+        }
+        self.write_queue.push(log)
+    }
+
+    ///Queues a log without ever blocking, it is dropped when the queue is full whatever the policy
+    pub(crate) fn try_save_log(&self, log: SimpleLog, source: S) -> Result<(), Error> {
+        if self.stop.load(Ordering::SeqCst) {
+            return Err(Error::WriterStopped);
+        }
+        self.write_queue.try_push(self.new_model(log, source)?)
+    }
+
+    ///Resolves once every log queued before the call has been written.
+    ///Err(LogsNotWritten) if any log queued since the previous flush was dropped or failed to write.
+    pub async fn flush(&self) -> Result<(), Error> {
+        self.write_queue.flush().await
+    }
+
+    ///Number of logs discarded by the backpressure policies, including logs from the tracing layer
+    ///which arrived while the queue was full
+    pub fn dropped_logs(&self) -> u64 {
+        self.write_queue.dropped()
+    }
+
+    ///Rows which can't be decoded are returned in SearchResults::failed instead of logs
+    pub fn search(
+        &self,
+        filter: &SearchFilter<S>,
+        sort: Sort,
+        pagination: Option<Pagination>,
+    ) -> Result<SearchResults<S>, Error> {
+        self.search_decoded(filter, sort, pagination)
+    }
+
+    ///Same as search with sources left as raw JSON, for reading logs whose source no longer
+    ///deserializes as S
+    pub fn search_raw(
+        &self,
+        filter: &SearchFilter<S>,
+        sort: Sort,
+        pagination: Option<Pagination>,
+    ) -> Result<SearchResults<serde_json::Value>, Error> {
+        self.search_decoded(filter, sort, pagination)
+    }
+
+    fn search_decoded<T: Serialize + DeserializeOwned>(
+        &self,
+        filter: &SearchFilter<S>,
+        sort: Sort,
+        pagination: Option<Pagination>,
+    ) -> Result<SearchResults<T>, Error> {
+        let mut query = filter.query()?;
+        let count_query = filter.query()?;
+        let mut sqlite_connection = self.connection_pool.reader();
         let total_count = count_query
             .select(count_star())
-            .first::<i64>(&mut sqlite_connection)
-            .map_err(|err| {
-                let err = Error::DieselResult(DieselResultError(err));
-                error!("{err}");
-                err
-            })?;
+            .first::<i64>(&mut *sqlite_connection)
+            .map_err(|err| filter.query_error(err))
+            .inspect_err(log_query_error)?;
+        //Before walks the sort order backwards, the page is put back in sort order after loading
+        let reversed = matches!(pagination, Some(Pagination::Before { .. }));
+        query = sort.apply(query, reversed);
+        let mut cursor_limit: Option<usize> = None;
         if let Some(pagination) = pagination {
             match pagination {
                 Pagination::Page { page, page_size } => {
-                    query = query
-                        .limit(page_size as i64)
-                        .offset(((page - 1) * page_size) as i64)
+                    let offset = page
+                        .saturating_sub(1)
+                        .checked_mul(page_size)
+                        .and_then(|offset| i64::try_from(offset).ok())
+                        .ok_or_else(|| {
+                            Error::InvalidPagination(format!("page {page} of size {page_size}"))
+                        })?;
+                    query = query.limit(sql_limit(page_size)?).offset(offset)
+                }
+                Pagination::After { cursor, limit } | Pagination::Before { cursor, limit } => {
+                    if let Some(cursor) = cursor {
+                        query = cursor.apply(query, sort.field, sort.is_descending(reversed));
+                    }
+                    query = query.limit(sql_limit(limit)?);
+                    cursor_limit = Some(limit);
                 }
             }
         }
-        match query.load::<LogModel>(&mut sqlite_connection) {
-            Ok(log_models) => {
+        match query.load::<LogModel>(&mut *sqlite_connection) {
+            Ok(mut log_models) => {
+                let next_cursor = match (cursor_limit, log_models.last()) {
+                    (Some(limit), Some(last)) if log_models.len() >= limit => {
+                        Some(Cursor::from_model(last))
+                    }
+                    _ => None,
+                };
+                if reversed {
+                    log_models.reverse();
+                }
+                let mut snippets_by_id = match filter.snippet_request() {
+                    Some((full_text, highlight)) => {
+                        let ids: Vec<i32> = log_models.iter().map(|model| model.id).collect();
+                        Some(load_snippets(
+                            &mut sqlite_connection,
+                            full_text,
+                            highlight,
+                            &ids,
+                        )?)
+                    }
+                    None => None,
+                };
                 //Not the most efficient way to do this
                 let mut logs = Vec::new();
-                let mut errors = Vec::new();
-                log_models
-                    .into_iter()
-                    .for_each(|model| match Log::<S>::from(model) {
-                        Ok(log_model) => logs.push(log_model),
-                        Err(err) => errors.push(err),
-                    });
-                if !errors.is_empty() {
-                    warn!("{}", Error::Errors(errors));
+                let mut snippets = Vec::new();
+                let mut failed = Vec::new();
+                log_models.into_iter().for_each(|model| {
+                    let id = model.id;
+                    match Log::<T>::decode(model) {
+                        Ok(log_model) => {
+                            logs.push(log_model);
+                            if let Some(snippets_by_id) = snippets_by_id.as_mut() {
+                                snippets.push(snippets_by_id.remove(&id).unwrap_or_default());
+                            }
+                        }
+                        Err((err, model)) => failed.push(FailedLog {
+                            id,
+                            error: err.to_string(),
+                            model: *model,
+                        }),
+                    }
+                });
+                if !failed.is_empty() {
+                    warn!(
+                        "{} logs in search results couldn't be decoded",
+                        failed.len()
+                    );
                 }
-                Ok((total_count, logs))
+                Ok(SearchResults {
+                    total_count,
+                    logs,
+                    next_cursor,
+                    snippets: snippets_by_id.map(|_| snippets),
+                    failed,
+                })
             }
             Err(err) => {
-                let err = Error::DieselResult(DieselResultError(err));
-                error!("{err}");
-                return Err(err);
+                let err = filter.query_error(err);
+                log_query_error(&err);
+                Err(err)
+            }
+        }
+    }
+
+    ///Every log of one trace from any source, oldest first.
+    ///Logs whose source no longer deserializes as S are in SearchResults::failed.
+    pub fn get_trace(&self, trace_id: &str) -> Result<SearchResults<S>, Error> {
+        self.search(
+            &SearchFilter::default().trace_id(trace_id),
+            Sort::new(SortField::Timestamp, SortDirection::Ascending),
+            None,
+        )
+    }
+
+    ///Counts the logs matching filter in each group, computed by SQLite.
+    ///Groups whose source no longer deserializes as S are left out with a warning.
+    pub fn aggregate(
+        &self,
+        filter: &SearchFilter<S>,
+        group_by: GroupBy,
+    ) -> Result<Vec<AggregateRow<S>>, Error> {
+        let rows = group_by
+            .load(filter, &mut self.connection_pool.reader())
+            .inspect_err(log_query_error)?;
+        let mut aggregates = Vec::with_capacity(rows.len());
+        let mut errors = Vec::new();
+        for row in rows {
+            match AggregateRow::from_row(row) {
+                Ok(aggregate) => aggregates.push(aggregate),
+                Err(err) => errors.push(err),
             }
         }
+        if !errors.is_empty() {
+            warn!("{}", Error::Errors(errors));
+        }
+        Ok(aggregates)
+    }
+
+    ///Every source that has logs stored, with when it was first and last seen and how many logs it has.
+    ///Sources which no longer deserialize as S are left out with a warning.
+    pub fn sources(&self) -> Result<Vec<SourceSummary<S>>, Error> {
+        let rows = SourceSummary::<S>::load_all(&mut self.connection_pool.reader())
+            .inspect_err(|err| error!("{err}"))?;
+        let mut sources = Vec::with_capacity(rows.len());
+        let mut errors = Vec::new();
+        for row in rows {
+            match SourceSummary::from_row(row) {
+                Ok(source) => sources.push(source),
+                Err(err) => errors.push(err),
+            }
+        }
+        if !errors.is_empty() {
+            warn!("{}", Error::Errors(errors));
+        }
+        Ok(sources)
+    }
+
+    ///Runs f on tokio's blocking thread pool, so SQLite I/O and lock waits don't hold up async workers
+    pub(crate) async fn run_blocking<T: Send + 'static>(
+        self: &Arc<Self>,
+        f: impl FnOnce(&Self) -> Result<T, Error> + Send + 'static,
+    ) -> Result<T, Error> {
+        let manager = self.to_owned();
+        tokio::task::spawn_blocking(move || f(&manager))
+            .await
+            .map_err(|err| Error::BlockingTask(err.to_string()))?
+    }
+
+    ///save_log for async code, waits without blocking under BackpressurePolicy::Block
+    pub async fn save_log_async(self: &Arc<Self>, log: SimpleLog, source: S) -> Result<(), Error> {
+        self.run_blocking(move |manager| manager.save_log(log, source))
+            .await
+    }
+
+    ///save_logs for async code
+    pub async fn save_logs_async(
+        self: &Arc<Self>,
+        logs: impl IntoIterator<Item = (SimpleLog, S)> + Send + 'static,
+    ) -> Result<Vec<Result<i32, Error>>, Error> {
+        self.run_blocking(move |manager| manager.save_logs(logs))
+            .await
+    }
+
+    ///search for async code
+    pub async fn search_async(
+        self: &Arc<Self>,
+        filter: SearchFilter<S>,
+        sort: Sort,
+        pagination: Option<Pagination>,
+    ) -> Result<SearchResults<S>, Error> {
+        self.run_blocking(move |manager| manager.search(&filter, sort, pagination))
+            .await
+    }
+
+    ///search_raw for async code
+    pub async fn search_raw_async(
+        self: &Arc<Self>,
+        filter: SearchFilter<S>,
+        sort: Sort,
+        pagination: Option<Pagination>,
+    ) -> Result<SearchResults<serde_json::Value>, Error> {
+        self.run_blocking(move |manager| manager.search_raw(&filter, sort, pagination))
+            .await
+    }
+
+    ///get_trace for async code
+    pub async fn get_trace_async(
+        self: &Arc<Self>,
+        trace_id: String,
+    ) -> Result<SearchResults<S>, Error> {
+        self.run_blocking(move |manager| manager.get_trace(&trace_id))
+            .await
+    }
+
+    ///aggregate for async code
+    pub async fn aggregate_async(
+        self: &Arc<Self>,
+        filter: SearchFilter<S>,
+        group_by: GroupBy,
+    ) -> Result<Vec<AggregateRow<S>>, Error> {
+        self.run_blocking(move |manager| manager.aggregate(&filter, group_by))
+            .await
+    }
+
+    ///sources for async code
+    pub async fn sources_async(self: &Arc<Self>) -> Result<Vec<SourceSummary<S>>, Error> {
+        self.run_blocking(|manager| manager.sources()).await
     }
 
     pub fn stop(&self) {
@@ -0,0 +1,169 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{TimeDelta, Utc};
+use diesel::{
+    dsl::count_star, sql_types::BigInt, ExpressionMethods, QueryDsl, QueryableByName, RunQueryDsl,
+    SqliteConnection,
+};
+
+use crate::{
+    database::pool::ConnectionPool,
+    error::{DieselResultError, Error},
+    logs::Level,
+    schema::log::{
+        self as log_table,
+        dsl::{id as id_db, level as level_db, log as log_data, timestamp as timestamp_db},
+    },
+};
+
+///Rows deleted per transaction, keeps the writer connection from being held for too long
+const PRUNE_CHUNK_SIZE: i64 = 5000;
+
+///Limits on what is kept in the log table, enforced periodically by a background task.
+///Every limit that is set applies, oldest logs are removed first.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    max_age: Option<Duration>,
+    level_max_age: HashMap<Level, Duration>,
+    max_rows: Option<u64>,
+    max_database_size: Option<u64>,
+}
+
+impl RetentionPolicy {
+    ///Removes logs older than max_age, unless their level has its own limit set with level_max_age
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    ///Removes logs of the given level older than max_age, overriding RetentionPolicy::max_age
+    pub fn level_max_age(mut self, level: Level, max_age: Duration) -> Self {
+        self.level_max_age.insert(level, max_age);
+        self
+    }
+
+    ///Keeps at most max_rows logs
+    pub fn max_rows(mut self, max_rows: u64) -> Self {
+        self.max_rows = Some(max_rows);
+        self
+    }
+
+    ///Removes logs until the pages in use by the database fit in max_database_size bytes.
+    ///Freed pages are reused by new logs, the file itself only shrinks with a VACUUM.
+    pub fn max_database_size(mut self, max_database_size: u64) -> Self {
+        self.max_database_size = Some(max_database_size);
+        self
+    }
+
+    ///Applies every limit of the policy, returning the number of logs removed
+    pub(crate) fn prune(&self, connection_pool: &ConnectionPool) -> Result<usize, Error> {
+        let now = Utc::now();
+        //None when max_age reaches back further than timestamps go, nothing is old enough to remove
+        let cutoff = |max_age: &Duration| {
+            TimeDelta::from_std(*max_age)
+                .ok()
+                .and_then(|max_age| now.checked_sub_signed(max_age))
+                .map(|cutoff| cutoff.timestamp_micros())
+        };
+        let mut removed = 0;
+        for (level, max_age) in self.level_max_age.iter() {
+            let Some(cutoff) = cutoff(max_age) else {
+                continue;
+            };
+            removed += delete_in_chunks(connection_pool, || {
+                log_data
+                    .select(id_db)
+                    .filter(level_db.eq(*level as i32))
+                    .filter(timestamp_db.lt(cutoff))
+                    .into_boxed()
+            })?;
+        }
+        if let Some(cutoff) = self.max_age.as_ref().and_then(cutoff) {
+            let overridden: Vec<i32> = self
+                .level_max_age
+                .keys()
+                .map(|level| *level as i32)
+                .collect();
+            removed += delete_in_chunks(connection_pool, || {
+                log_data
+                    .select(id_db)
+                    .filter(level_db.ne_all(overridden.to_owned()))
+                    .filter(timestamp_db.lt(cutoff))
+                    .into_boxed()
+            })?;
+        }
+        if let Some(max_rows) = self.max_rows {
+            let row_count = log_data
+                .select(count_star())
+                .first::<i64>(&mut *connection_pool.writer())
+                .map_err(|err| Error::DieselResult(DieselResultError(err)))?;
+            let mut excess = (row_count as u64).saturating_sub(max_rows) as i64;
+            while excess > 0 {
+                let deleted =
+                    delete_oldest(&mut connection_pool.writer(), excess.min(PRUNE_CHUNK_SIZE))?;
+                if deleted == 0 {
+                    break;
+                }
+                excess -= deleted as i64;
+                removed += deleted;
+            }
+        }
+        if let Some(max_database_size) = self.max_database_size {
+            while used_bytes(&mut connection_pool.writer())? > max_database_size {
+                let deleted = delete_oldest(&mut connection_pool.writer(), PRUNE_CHUNK_SIZE)?;
+                if deleted == 0 {
+                    break;
+                }
+                removed += deleted;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+///Deletes every log selected by query, one chunk per writer lock
+fn delete_in_chunks(
+    connection_pool: &ConnectionPool,
+    query: impl Fn()
+        -> log_table::BoxedQuery<'static, diesel::sqlite::Sqlite, diesel::sql_types::Integer>,
+) -> Result<usize, Error> {
+    let mut removed = 0;
+    loop {
+        let deleted =
+            diesel::delete(log_data.filter(id_db.eq_any(query().limit(PRUNE_CHUNK_SIZE))))
+                .execute(&mut *connection_pool.writer())
+                .map_err(|err| Error::DieselResult(DieselResultError(err)))?;
+        removed += deleted;
+        if deleted < PRUNE_CHUNK_SIZE as usize {
+            return Ok(removed);
+        }
+    }
+}
+
+fn delete_oldest(connection: &mut SqliteConnection, count: i64) -> Result<usize, Error> {
+    let oldest = log_data
+        .select(id_db)
+        .order((timestamp_db.asc(), id_db.asc()))
+        .limit(count)
+        .into_boxed();
+    diesel::delete(log_data.filter(id_db.eq_any(oldest)))
+        .execute(connection)
+        .map_err(|err| Error::DieselResult(DieselResultError(err)))
+}
+
+#[derive(QueryableByName)]
+struct UsedBytes {
+    #[diesel(sql_type = BigInt)]
+    used_bytes: i64,
+}
+
+///Size of the pages holding data, excluding pages on the freelist
+fn used_bytes(connection: &mut SqliteConnection) -> Result<u64, Error> {
+    diesel::sql_query(
+        "SELECT (page_count - freelist_count) * page_size AS used_bytes \
+         FROM pragma_page_count(), pragma_freelist_count(), pragma_page_size()",
+    )
+    .get_result::<UsedBytes>(connection)
+    .map(|row| row.used_bytes.max(0) as u64)
+    .map_err(|err| Error::DieselResult(DieselResultError(err)))
+}
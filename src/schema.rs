@@ -4,9 +4,17 @@ diesel::table! {
     log (id) {
         id -> Integer,
         source -> Text,
-        timestamp -> Text,
-        level -> Text,
+        timestamp -> BigInt,
+        level -> Integer,
         location -> Text,
         content -> Text,
+        ingest_id -> Nullable<Text>,
+        fields -> Nullable<Text>,
+        trace_id -> Nullable<Text>,
+        span_id -> Nullable<Text>,
+        parent_span_id -> Nullable<Text>,
+        span_name -> Nullable<Text>,
+        source_path -> Nullable<Text>,
+        heartbeat -> Bool,
     }
 }
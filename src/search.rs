@@ -0,0 +1,629 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    ops::{Bound, RangeBounds},
+    str::FromStr,
+};
+
+use chrono::{DateTime, Utc};
+use diesel::{
+    dsl::sql,
+    sql_types::{BigInt, Bool, Double, Integer, Text},
+    sqlite::Sqlite,
+    BoolExpressionMethods, EscapeExpressionMethods, ExpressionMethods, QueryDsl, QueryableByName,
+    RunQueryDsl, SqliteConnection, TextExpressionMethods,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::model::LogModel,
+    error::{DieselResultError, Error},
+    logs::{FieldValue, Fields, Level, Log},
+    schema::log::{
+        self as log_table,
+        dsl::{
+            content as content_db, heartbeat as heartbeat_db, id as id_db, level as level_db,
+            log as log_data, source as source_db, source_path as source_path_db,
+            timestamp as timestamp_db, trace_id as trace_id_db,
+        },
+    },
+    serialize_or_return_err,
+    source::{encode_path, prefix_upper_bound},
+};
+
+///Which logs LogManager::search returns, every condition that is set has to match
+pub struct SearchFilter<S> {
+    source: Option<S>,
+    ///Encoded
+    source_prefix: Option<String>,
+    levels: Vec<Level>,
+    min_level: Option<Level>,
+    content: Option<String>,
+    full_text: Option<String>,
+    highlight: Option<Highlight>,
+    from: Bound<DateTime<Utc>>,
+    to: Bound<DateTime<Utc>>,
+    fields: Vec<FieldFilter>,
+    trace_id: Option<String>,
+    heartbeat: Option<bool>,
+}
+
+impl<S> Default for SearchFilter<S> {
+    fn default() -> Self {
+        Self {
+            source: None,
+            source_prefix: None,
+            levels: Vec::new(),
+            min_level: None,
+            content: None,
+            full_text: None,
+            highlight: None,
+            from: Bound::Unbounded,
+            to: Bound::Unbounded,
+            fields: Vec::new(),
+            trace_id: None,
+            heartbeat: None,
+        }
+    }
+}
+
+impl<S: Serialize> SearchFilter<S> {
+    pub fn source(mut self, source: S) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    ///Matches sources whose path starts with prefix, see source::Source,
+    ///e.g. `["Agent"]` for every LogSource::Agent or `["Agent", "5"]` for LogSource::Agent(5).
+    ///An empty prefix matches every source.
+    pub fn source_prefix<C: AsRef<str>>(mut self, prefix: &[C]) -> Self {
+        self.source_prefix = match prefix.is_empty() {
+            true => None,
+            false => Some(encode_path(prefix)),
+        };
+        self
+    }
+
+    ///Matches any of the given levels, an empty slice matches every level
+    pub fn levels(mut self, levels: &[Level]) -> Self {
+        self.levels = levels.to_vec();
+        self
+    }
+
+    ///Matches logs at or above the given level
+    pub fn min_level(mut self, min_level: Level) -> Self {
+        self.min_level = Some(min_level);
+        self
+    }
+
+    ///Case insensitive substring match against the log content, % and _ are matched literally
+    pub fn content(mut self, content: impl Into<String>) -> Self {
+        self.content = Some(content.into());
+        self
+    }
+
+    ///Full-text match against the log content using SQLite FTS5 query syntax,
+    ///e.g. `"connection reset" AND agent* NOT timeout`
+    pub fn full_text(mut self, query: impl Into<String>) -> Self {
+        self.full_text = Some(query.into());
+        self
+    }
+
+    ///Returns a snippet of the content with the full_text matches marked for each log
+    pub fn highlight(mut self, highlight: Highlight) -> Self {
+        self.highlight = Some(highlight);
+        self
+    }
+
+    ///Full-text query and highlighting options when snippets were requested
+    pub(crate) fn snippet_request(&self) -> Option<(&str, &Highlight)> {
+        match (&self.full_text, &self.highlight) {
+            (Some(query), Some(highlight)) => Some((query, highlight)),
+            _ => None,
+        }
+    }
+
+    ///Lower bound on the log timestamp
+    pub fn from(mut self, from: Bound<DateTime<Utc>>) -> Self {
+        self.from = from;
+        self
+    }
+
+    ///Upper bound on the log timestamp
+    pub fn to(mut self, to: Bound<DateTime<Utc>>) -> Self {
+        self.to = to;
+        self
+    }
+
+    ///Matches logs whose structured field key is equal to value
+    pub fn field(self, key: impl Into<String>, value: impl Into<FieldValue>) -> Self {
+        let value = value.into();
+        self.field_range(key, Bound::Included(value.clone()), Bound::Included(value))
+    }
+
+    ///Matches logs whose structured field key is within from and to, values of a different kind
+    ///than the bounds never match, e.g. a string field against a numeric range.
+    ///Unbounded on both ends matches every log which has the field.
+    pub fn field_range(
+        mut self,
+        key: impl Into<String>,
+        from: Bound<FieldValue>,
+        to: Bound<FieldValue>,
+    ) -> Self {
+        self.fields.push(FieldFilter {
+            key: key.into(),
+            from,
+            to,
+        });
+        self
+    }
+
+    ///Matches logs of one trace, see SpanContext::trace_id
+    pub fn trace_id(mut self, trace_id: impl Into<String>) -> Self {
+        self.trace_id = Some(trace_id.into());
+        self
+    }
+
+    ///Matches only the logs written by the heartbeat monitor, or with false only every other log.
+    ///Both are matched by default.
+    pub fn heartbeat(mut self, heartbeat: bool) -> Self {
+        self.heartbeat = Some(heartbeat);
+        self
+    }
+
+    ///Builds a query over the log table with every condition of this filter applied
+    pub(crate) fn query(&self) -> Result<log_table::BoxedQuery<'static, Sqlite>, Error> {
+        let mut query = log_data.into_boxed();
+        if let Some(source) = &self.source {
+            query = query.filter(source_db.eq(serialize_or_return_err!(source, "source")));
+        }
+        if let Some(source_prefix) = &self.source_prefix {
+            query = query.filter(
+                source_path_db
+                    .ge(source_prefix.to_owned())
+                    .and(source_path_db.lt(prefix_upper_bound(source_prefix))),
+            );
+        }
+        if !self.levels.is_empty() {
+            let levels: Vec<i32> = self.levels.iter().map(|level| *level as i32).collect();
+            query = query.filter(level_db.eq_any(levels));
+        }
+        if let Some(min_level) = self.min_level {
+            query = query.filter(level_db.ge(min_level as i32));
+        }
+        if let Some(content) = &self.content {
+            let escaped = content
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            query = query.filter(content_db.like(format!("%{escaped}%")).escape('\\'));
+        }
+        if let Some(full_text) = &self.full_text {
+            query = query.filter(
+                sql::<Bool>("log.id IN (SELECT rowid FROM log_fts WHERE log_fts MATCH ")
+                    .bind::<Text, _>(full_text.to_owned())
+                    .sql(")"),
+            );
+        }
+        match self.from {
+            Bound::Included(from) => query = query.filter(timestamp_db.ge(from.timestamp_micros())),
+            Bound::Excluded(from) => query = query.filter(timestamp_db.gt(from.timestamp_micros())),
+            Bound::Unbounded => {}
+        }
+        match self.to {
+            Bound::Included(to) => query = query.filter(timestamp_db.le(to.timestamp_micros())),
+            Bound::Excluded(to) => query = query.filter(timestamp_db.lt(to.timestamp_micros())),
+            Bound::Unbounded => {}
+        }
+        if let Some(trace_id) = &self.trace_id {
+            query = query.filter(trace_id_db.eq(trace_id.to_owned()));
+        }
+        if let Some(heartbeat) = self.heartbeat {
+            query = query.filter(heartbeat_db.eq(heartbeat));
+        }
+        for field in self.fields.iter() {
+            query = field.apply(query)?;
+        }
+        Ok(query)
+    }
+
+    ///Error of running a query built by query, FTS5 rejecting full_text is a mistake in the filter
+    ///rather than a database failure
+    pub(crate) fn query_error(&self, err: diesel::result::Error) -> Error {
+        match (&self.full_text, &err) {
+            (Some(full_text), diesel::result::Error::DatabaseError(_, info))
+                if FULL_TEXT_QUERY_ERRORS
+                    .iter()
+                    .any(|message| info.message().starts_with(message)) =>
+            {
+                Error::InvalidFullTextQuery(format!("{full_text}: {}", info.message()))
+            }
+            _ => Error::DieselResult(DieselResultError(err)),
+        }
+    }
+
+    ///Evaluates the filter in memory against logs as they are written, full_text can only run in SQLite
+    pub(crate) fn matcher(&self) -> Result<LogMatcher, Error> {
+        if self.full_text.is_some() {
+            return Err(Error::UnsupportedFilter("full_text".into()));
+        }
+        Ok(LogMatcher {
+            source: match &self.source {
+                Some(source) => Some(serialize_or_return_err!(source, "source")),
+                None => None,
+            },
+            source_prefix: self.source_prefix.to_owned(),
+            levels: self.levels.iter().map(|level| *level as i32).collect(),
+            min_level: self.min_level.map(|level| level as i32),
+            content: self
+                .content
+                .as_ref()
+                .map(|content| content.to_ascii_lowercase()),
+            from: self.from.map(|from| from.timestamp_micros()),
+            to: self.to.map(|to| to.timestamp_micros()),
+            fields: self.fields.to_owned(),
+            trace_id: self.trace_id.to_owned(),
+            heartbeat: self.heartbeat,
+        })
+    }
+}
+
+///In memory equivalent of SearchFilter::query
+pub(crate) struct LogMatcher {
+    source: Option<String>,
+    source_prefix: Option<String>,
+    levels: Vec<i32>,
+    min_level: Option<i32>,
+    ///Lowercase to match LIKE, which is case insensitive for ASCII
+    content: Option<String>,
+    from: Bound<i64>,
+    to: Bound<i64>,
+    fields: Vec<FieldFilter>,
+    trace_id: Option<String>,
+    heartbeat: Option<bool>,
+}
+
+impl LogMatcher {
+    pub fn matches(&self, log: &LogModel) -> bool {
+        self.source
+            .as_ref()
+            .is_none_or(|expected| *expected == log.source)
+            && self.source_prefix.as_ref().is_none_or(|prefix| {
+                log.source_path
+                    .as_ref()
+                    .is_some_and(|path| path.starts_with(prefix.as_str()))
+            })
+            && (self.levels.is_empty() || self.levels.contains(&log.level))
+            && self
+                .min_level
+                .is_none_or(|min_level| log.level >= min_level)
+            && self
+                .content
+                .as_ref()
+                .is_none_or(|expected| log.content.to_ascii_lowercase().contains(expected.as_str()))
+            && (self.from, self.to).contains(&log.timestamp)
+            && self.matches_fields(log.fields.as_deref())
+            && self
+                .trace_id
+                .as_ref()
+                .is_none_or(|expected| log.trace_id.as_ref() == Some(expected))
+            && self
+                .heartbeat
+                .is_none_or(|expected| log.heartbeat == expected)
+    }
+
+    fn matches_fields(&self, fields: Option<&str>) -> bool {
+        if self.fields.is_empty() {
+            return true;
+        }
+        let Some(Ok(fields)) = fields.map(serde_json::from_str::<Fields>) else {
+            return false;
+        };
+        self.fields.iter().all(|filter| {
+            fields
+                .get(&filter.key)
+                .is_some_and(|value| filter.contains(value))
+        })
+    }
+}
+
+///Condition on one structured field, see SearchFilter::field_range
+#[derive(Clone)]
+struct FieldFilter {
+    key: String,
+    from: Bound<FieldValue>,
+    to: Bound<FieldValue>,
+}
+
+impl FieldFilter {
+    ///Restricts the query to logs with the field within the bounds, the JSON type of the stored
+    ///value has to match the bound since SQLite orders every number before every string
+    fn apply(
+        &self,
+        mut query: log_table::BoxedQuery<'static, Sqlite>,
+    ) -> Result<log_table::BoxedQuery<'static, Sqlite>, Error> {
+        //Keys are quoted in the path so dots etc are matched literally, which rules out quotes
+        if self.key.contains('"') {
+            return Err(Error::InvalidFieldKey(self.key.to_owned()));
+        }
+        let path = format!("$.\"{}\"", self.key);
+        if let (Bound::Unbounded, Bound::Unbounded) = (&self.from, &self.to) {
+            query = query.filter(
+                sql::<Bool>("json_type(log.fields, ")
+                    .bind::<Text, _>(path.to_owned())
+                    .sql(") IS NOT NULL"),
+            );
+        }
+        for (bound, operator) in [
+            (&self.from, bound_operator(&self.from, ">")),
+            (&self.to, bound_operator(&self.to, "<")),
+        ] {
+            let (Bound::Included(value) | Bound::Excluded(value)) = bound else {
+                continue;
+            };
+            let types = match value {
+                FieldValue::Bool(_) => "('true', 'false')",
+                FieldValue::I64(_) | FieldValue::U64(_) | FieldValue::F64(_) => {
+                    "('integer', 'real')"
+                }
+                FieldValue::String(_) => "('text')",
+            };
+            let condition = sql::<Bool>("json_type(log.fields, ")
+                .bind::<Text, _>(path.to_owned())
+                .sql(&format!(") IN {types} AND json_extract(log.fields, "))
+                .bind::<Text, _>(path.to_owned())
+                .sql(&format!(") {operator} "));
+            //json_extract returns true and false as 1 and 0
+            query = match value {
+                FieldValue::Bool(value) => {
+                    query.filter(condition.bind::<Integer, _>(*value as i32))
+                }
+                FieldValue::I64(value) => query.filter(condition.bind::<BigInt, _>(*value)),
+                FieldValue::U64(value) => match i64::try_from(*value) {
+                    Ok(value) => query.filter(condition.bind::<BigInt, _>(value)),
+                    Err(_) => query.filter(condition.bind::<Double, _>(*value as f64)),
+                },
+                FieldValue::F64(value) => query.filter(condition.bind::<Double, _>(*value)),
+                FieldValue::String(value) => {
+                    query.filter(condition.bind::<Text, _>(value.to_owned()))
+                }
+            };
+        }
+        Ok(query)
+    }
+
+    fn contains(&self, value: &FieldValue) -> bool {
+        let above = match &self.from {
+            Bound::Included(from) => {
+                matches!(
+                    value.compare(from),
+                    Some(Ordering::Greater | Ordering::Equal)
+                )
+            }
+            Bound::Excluded(from) => value.compare(from) == Some(Ordering::Greater),
+            Bound::Unbounded => true,
+        };
+        let below = match &self.to {
+            Bound::Included(to) => {
+                matches!(value.compare(to), Some(Ordering::Less | Ordering::Equal))
+            }
+            Bound::Excluded(to) => value.compare(to) == Some(Ordering::Less),
+            Bound::Unbounded => true,
+        };
+        above && below
+    }
+}
+
+///SQL comparison for a bound, greater_or_less being ">" for lower bounds and "<" for upper bounds
+fn bound_operator<T>(bound: &Bound<T>, greater_or_less: &str) -> String {
+    match bound {
+        Bound::Included(_) => format!("{greater_or_less}="),
+        _ => greater_or_less.to_string(),
+    }
+}
+
+///How matches are marked in full-text snippets, see SQLite's FTS5 snippet function
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Highlight {
+    ///Inserted before each match
+    pub open: String,
+    ///Inserted after each match
+    pub close: String,
+    ///Inserted where the content was cut off
+    pub ellipsis: String,
+    ///Maximum number of tokens in a snippet, SQLite caps this at 64
+    pub max_tokens: u8,
+}
+
+impl Default for Highlight {
+    fn default() -> Self {
+        Self {
+            open: "[".into(),
+            close: "]".into(),
+            ellipsis: "...".into(),
+            max_tokens: 16,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortField {
+    Id,
+    ///Ties are broken by id
+    #[default]
+    Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortDirection {
+    Ascending,
+    #[default]
+    Descending,
+}
+
+///Order of search results, defaults to newest first
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Sort {
+    pub field: SortField,
+    pub direction: SortDirection,
+}
+
+impl Sort {
+    pub fn new(field: SortField, direction: SortDirection) -> Self {
+        Self { field, direction }
+    }
+
+    ///Orders the query by this sort, or by its opposite when reversed
+    pub(crate) fn apply(
+        &self,
+        query: log_table::BoxedQuery<'static, Sqlite>,
+        reversed: bool,
+    ) -> log_table::BoxedQuery<'static, Sqlite> {
+        match (self.field, self.is_descending(reversed)) {
+            (SortField::Id, false) => query.order(id_db.asc()),
+            (SortField::Id, true) => query.order(id_db.desc()),
+            (SortField::Timestamp, false) => query.order((timestamp_db.asc(), id_db.asc())),
+            (SortField::Timestamp, true) => query.order((timestamp_db.desc(), id_db.desc())),
+        }
+    }
+
+    pub(crate) fn is_descending(&self, reversed: bool) -> bool {
+        (self.direction == SortDirection::Descending) != reversed
+    }
+}
+
+///Opaque position of a log in the search results, used by Pagination::After and Pagination::Before
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(into = "String", try_from = "String")]
+pub struct Cursor {
+    timestamp: i64,
+    id: i32,
+}
+
+impl Cursor {
+    pub(crate) fn from_model(model: &LogModel) -> Self {
+        Self {
+            timestamp: model.timestamp,
+            id: model.id,
+        }
+    }
+
+    ///Restricts the query to logs which come after this cursor when ordered by field
+    pub(crate) fn apply(
+        &self,
+        query: log_table::BoxedQuery<'static, Sqlite>,
+        field: SortField,
+        descending: bool,
+    ) -> log_table::BoxedQuery<'static, Sqlite> {
+        match (field, descending) {
+            (SortField::Id, false) => query.filter(id_db.gt(self.id)),
+            (SortField::Id, true) => query.filter(id_db.lt(self.id)),
+            (SortField::Timestamp, false) => query.filter(
+                timestamp_db
+                    .gt(self.timestamp)
+                    .or(timestamp_db.eq(self.timestamp).and(id_db.gt(self.id))),
+            ),
+            (SortField::Timestamp, true) => query.filter(
+                timestamp_db
+                    .lt(self.timestamp)
+                    .or(timestamp_db.eq(self.timestamp).and(id_db.lt(self.id))),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.timestamp, self.id)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidCursor(value.to_string());
+        let (timestamp, id) = value.split_once(':').ok_or_else(invalid)?;
+        Ok(Self {
+            timestamp: timestamp.parse().map_err(|_| invalid())?,
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl From<Cursor> for String {
+    fn from(value: Cursor) -> Self {
+        value.to_string()
+    }
+}
+
+impl TryFrom<String> for Cursor {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Error> {
+        value.parse()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchResults<S> {
+    ///Number of logs matching the filter, ignoring pagination
+    pub total_count: i64,
+    pub logs: Vec<Log<S>>,
+    ///Continues cursor pagination in the same direction, None once there is nothing further to read
+    pub next_cursor: Option<Cursor>,
+    ///Highlighted snippet for each log, in the same order as logs, when SearchFilter::highlight was set
+    pub snippets: Option<Vec<String>>,
+    ///Rows on this page which couldn't be decoded, e.g. sources stored by an older version of S.
+    ///They count towards total_count, LogManager::search_raw can still read their sources.
+    pub failed: Vec<FailedLog>,
+}
+
+///A stored row which couldn't be decoded into a Log
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FailedLog {
+    pub id: i32,
+    pub error: String,
+    pub model: LogModel,
+}
+
+#[derive(QueryableByName)]
+struct SnippetRow {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+    #[diesel(sql_type = Text)]
+    snippet: String,
+}
+
+///Start of the messages of the errors FTS5 raises for malformed queries
+const FULL_TEXT_QUERY_ERRORS: [&str; 5] = [
+    "fts5: ",
+    "unterminated string",
+    "no such column: ",
+    "unknown special query",
+    "expected integer",
+];
+
+///Runs the full-text query again restricted to ids to get the snippet of each match
+pub(crate) fn load_snippets(
+    connection: &mut SqliteConnection,
+    full_text: &str,
+    highlight: &Highlight,
+    ids: &[i32],
+) -> Result<HashMap<i32, String>, Error> {
+    let ids = serialize_or_return_err!(ids, "ids");
+    let rows = diesel::sql_query(
+        "SELECT rowid AS id, snippet(log_fts, 0, ?, ?, ?, ?) AS snippet FROM log_fts \
+         WHERE log_fts MATCH ? AND rowid IN (SELECT value FROM json_each(?))",
+    )
+    .bind::<Text, _>(&highlight.open)
+    .bind::<Text, _>(&highlight.close)
+    .bind::<Text, _>(&highlight.ellipsis)
+    .bind::<Integer, _>(highlight.max_tokens as i32)
+    .bind::<Text, _>(full_text)
+    .bind::<Text, _>(ids)
+    .load::<SnippetRow>(connection)
+    .map_err(|err| Error::DieselResult(DieselResultError(err)))?;
+    Ok(rows.into_iter().map(|row| (row.id, row.snippet)).collect())
+}
@@ -0,0 +1,176 @@
+use std::fmt;
+
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, SqliteConnection};
+use serde::{
+    de::{DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    Deserializer, Serialize,
+};
+use tracing::warn;
+
+use crate::{
+    error::{DieselResultError, Error},
+    schema::log::dsl::{log as log_data, source as source_db, source_path as source_path_db},
+};
+
+///Ends every component of a stored source_path, so a prefix only matches whole components
+pub(crate) const PATH_SEPARATOR: char = '\x1F';
+
+///Source of a log decomposed into components from the most to the least general so search can match
+///by prefix, e.g. LogSource::Agent(5) is ["Agent", "5"] and matches the prefixes ["Agent"] and ["Agent", "5"].
+///Implemented for every Serialize type with default_path, Builder::source_path overrides it.
+///Paths are stored when logs are written so existing logs keep theirs if the path of their source changes.
+pub trait Source {
+    fn source_path(&self) -> Vec<String>;
+}
+
+impl<T: Serialize + ?Sized> Source for T {
+    fn source_path(&self) -> Vec<String> {
+        default_path(self)
+    }
+}
+
+///Source::source_path following the serde representation, enum variants contribute their name
+///followed by their fields and structs each field name followed by its value, in declaration order
+pub fn default_path<S: Serialize + ?Sized>(source: &S) -> Vec<String> {
+    match serde_json::to_string(source) {
+        Ok(source) => json_path(&source).unwrap_or_default(),
+        Err(err) => {
+            warn!("Error serializing source for its path: {err}");
+            Vec::new()
+        }
+    }
+}
+
+///Builder::source_path over the stored JSON of a source, None when it doesn't deserialize
+pub(crate) type SourcePath = Box<dyn Fn(&str) -> Option<Vec<String>> + Send + Sync>;
+
+///Encoded path of a source as stored, None when it can't be decoded
+pub(crate) fn stored_path(source: &str, source_path: Option<&SourcePath>) -> Option<String> {
+    let path = match source_path {
+        Some(source_path) => source_path(source),
+        None => json_path(source),
+    }?;
+    Some(encode_path(&path))
+}
+
+///default_path of a source as stored, None if it isn't valid JSON
+pub(crate) fn json_path(source: &str) -> Option<Vec<String>> {
+    let mut path = Vec::new();
+    let mut deserializer = serde_json::Deserializer::from_str(source);
+    PathSeed(&mut path).deserialize(&mut deserializer).ok()?;
+    Some(path)
+}
+
+///Walks a JSON value in document order, unlike serde_json::Value whose objects are sorted by key
+struct PathSeed<'a>(&'a mut Vec<String>);
+
+impl<'de> DeserializeSeed<'de> for PathSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for PathSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any JSON value")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<(), E> {
+        self.0.push(value.to_string());
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<(), E> {
+        self.0.push(value.to_string());
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<(), E> {
+        self.0.push(value.to_string());
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<(), E> {
+        self.0.push(value.to_string());
+        Ok(())
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<(), E> {
+        self.0.push(value.to_owned());
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq.next_element_seed(PathSeed(&mut *self.0))?.is_some() {}
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            self.0.push(key);
+            map.next_value_seed(PathSeed(&mut *self.0))?;
+        }
+        Ok(())
+    }
+}
+
+///Stored form of a path, each component followed by PATH_SEPARATOR which components can't contain
+pub(crate) fn encode_path<C: AsRef<str>>(path: &[C]) -> String {
+    path.iter().fold(String::new(), |mut encoded, component| {
+        encoded.extend(
+            component
+                .as_ref()
+                .chars()
+                .filter(|character| *character != PATH_SEPARATOR),
+        );
+        encoded.push(PATH_SEPARATOR);
+        encoded
+    })
+}
+
+///Stored paths starting with prefix sort at or after the encoded prefix and before this,
+///which lets the prefix match use the source_path index
+pub(crate) fn prefix_upper_bound(encoded_prefix: &str) -> String {
+    let mut upper_bound = encoded_prefix.to_string();
+    upper_bound.pop();
+    upper_bound.push((PATH_SEPARATOR as u8 + 1) as char);
+    upper_bound
+}
+
+///Fills in source_path for logs written before it was stored, path_of returns None for sources
+///which can't be decoded, those are left out with a warning. Returns the number of logs updated.
+pub(crate) fn backfill_paths(
+    connection: &mut SqliteConnection,
+    path_of: impl Fn(&str) -> Option<String>,
+) -> Result<usize, Error> {
+    let sources: Vec<String> = log_data
+        .filter(source_path_db.is_null())
+        .select(source_db)
+        .distinct()
+        .load(connection)
+        .map_err(|err| Error::DieselResult(DieselResultError(err)))?;
+    let mut updated = 0;
+    for source in sources {
+        let Some(path) = path_of(&source) else {
+            warn!("Not storing the path of source {source} which failed to deserialize");
+            continue;
+        };
+        updated += diesel::update(
+            log_data
+                .filter(source_db.eq(&source))
+                .filter(source_path_db.is_null()),
+        )
+        .set(source_path_db.eq(path))
+        .execute(connection)
+        .map_err(|err| Error::DieselResult(DieselResultError(err)))?;
+    }
+    Ok(updated)
+}
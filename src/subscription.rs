@@ -0,0 +1,45 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+use crate::{database::model::LogModel, error::Error, logs::Log, search::LogMatcher};
+
+///Live feed of logs written after LogManager::subscribe was called, narrowed down by its filter
+pub struct Subscription<S> {
+    receiver: broadcast::Receiver<Arc<LogModel>>,
+    matcher: LogMatcher,
+    _phantom: PhantomData<S>,
+}
+
+impl<S: Serialize + DeserializeOwned> Subscription<S> {
+    pub(crate) fn new(receiver: broadcast::Receiver<Arc<LogModel>>, matcher: LogMatcher) -> Self {
+        Self {
+            receiver,
+            matcher,
+            _phantom: PhantomData,
+        }
+    }
+
+    ///Waits for the next matching log.
+    ///Returns Error::SubscriptionLagged with the number of skipped logs when this subscriber fell
+    ///too far behind, receiving can carry on from the oldest log still buffered.
+    ///Returns Error::SubscriptionClosed once the manager has stopped.
+    pub async fn recv(&mut self) -> Result<Log<S>, Error> {
+        loop {
+            let log = match self.receiver.recv().await {
+                Ok(log) => log,
+                Err(RecvError::Lagged(skipped)) => return Err(Error::SubscriptionLagged(skipped)),
+                Err(RecvError::Closed) => return Err(Error::SubscriptionClosed),
+            };
+            if !self.matcher.matches(&log) {
+                continue;
+            }
+            match Log::<S>::from(LogModel::clone(&log)) {
+                Ok(log) => return Ok(log),
+                Err(err) => warn!("Skipping log {} in subscription: {err}", log.id),
+            }
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    collections::VecDeque,
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use diesel::{Connection, OptionalExtension, RunQueryDsl, SqliteConnection};
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{watch, Notify},
+    time::Instant,
+};
+
+use crate::{
+    database::model::{LogModel, NewLogModel},
+    error::{DieselResultError, Error},
+    schema::log as log_table,
+};
+
+///What save_log does when the write queue is full
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackpressurePolicy {
+    ///Blocks the caller until the writer has made room, logs from the tracing layer are dropped instead
+    Block,
+    ///Discards the oldest queued log to make room for the new one
+    DropOldest,
+    ///Discards the log being saved
+    DropNewest,
+}
+
+struct QueueState {
+    pending: VecDeque<(u64, NewLogModel)>,
+    ///Sequence number of the most recently queued log
+    last_queued: u64,
+    dropped: u64,
+    ///Sequence number of the most recent log which was dropped or failed to write
+    last_lost: u64,
+    ///Target of the most recent flush, the next flush reports losses after it
+    flushed_through: u64,
+    ///A log was discarded without being queued since the most recent flush started
+    dropped_unqueued: bool,
+    flushes: Vec<PendingFlush>,
+    flush_requested: bool,
+    closed: bool,
+}
+
+///A flush waiting on the logs after from up to target
+struct PendingFlush {
+    from: u64,
+    target: u64,
+    lost: Arc<AtomicBool>,
+}
+
+impl QueueState {
+    ///Records that the logs from first to last won't be written
+    fn lose(&mut self, first: u64, last: u64) {
+        self.last_lost = self.last_lost.max(last);
+        for flush in &self.flushes {
+            if flush.from < last && first <= flush.target {
+                flush.lost.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+///Bounded queue between save_log and the background writer task
+pub(crate) struct WriteQueue {
+    state: Mutex<QueueState>,
+    not_full: Condvar,
+    not_empty: Notify,
+    ///Sequence number of the most recently committed (or discarded) log
+    committed: watch::Sender<u64>,
+    capacity: usize,
+    policy: BackpressurePolicy,
+}
+
+impl WriteQueue {
+    pub fn new(capacity: usize, policy: BackpressurePolicy) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                pending: VecDeque::new(),
+                last_queued: 0,
+                dropped: 0,
+                last_lost: 0,
+                flushed_through: 0,
+                dropped_unqueued: false,
+                flushes: Vec::new(),
+                flush_requested: false,
+                closed: false,
+            }),
+            not_full: Condvar::new(),
+            not_empty: Notify::new(),
+            committed: watch::channel(0).0,
+            capacity: capacity.max(1),
+            policy,
+        }
+    }
+
+    ///Under the Block policy this parks the calling thread until the writer makes room,
+    ///which never happens if the writer runs on the same thread
+    pub fn push(&self, log: NewLogModel) -> Result<(), Error> {
+        self.push_with(log, true)
+    }
+
+    ///Like push, but drops the log when the queue is full instead of blocking under the Block policy
+    pub fn try_push(&self, log: NewLogModel) -> Result<(), Error> {
+        self.push_with(log, false)
+    }
+
+    fn push_with(&self, log: NewLogModel, wait: bool) -> Result<(), Error> {
+        let mut state = self.state.lock();
+        if state.closed {
+            return Err(Error::WriterStopped);
+        }
+        if state.pending.len() >= self.capacity {
+            match self.policy {
+                BackpressurePolicy::Block if wait => {
+                    while state.pending.len() >= self.capacity && !state.closed {
+                        self.not_full.wait(&mut state);
+                    }
+                    if state.closed {
+                        return Err(Error::WriterStopped);
+                    }
+                }
+                BackpressurePolicy::DropOldest => {
+                    if let Some((sequence, _)) = state.pending.pop_front() {
+                        state.lose(sequence, sequence);
+                    }
+                    state.dropped += 1;
+                }
+                BackpressurePolicy::Block | BackpressurePolicy::DropNewest => {
+                    //Never gets a sequence number, so flushes already pending aren't waiting on it
+                    state.dropped += 1;
+                    state.dropped_unqueued = true;
+                    return Ok(());
+                }
+            }
+        }
+        state.last_queued += 1;
+        let sequence = state.last_queued;
+        state.pending.push_back((sequence, log));
+        drop(state);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.state.lock().dropped
+    }
+
+    ///Asks the writer to commit everything queued so far without waiting for a full batch and waits for it.
+    ///Err(LogsNotWritten) if any log queued since the previous flush was dropped or failed to write.
+    pub async fn flush(&self) -> Result<(), Error> {
+        let mut committed = self.committed.subscribe();
+        let (target, lost) = {
+            let mut state = self.state.lock();
+            let target = state.last_queued;
+            let already_committed = *committed.borrow() >= target;
+            if state.closed && !already_committed {
+                return Err(Error::WriterStopped);
+            }
+            let from = state.flushed_through;
+            state.flushed_through = from.max(target);
+            //Losses from here on are recorded by lose while the flush is pending
+            let lost = Arc::new(AtomicBool::new(
+                state.last_lost > from || std::mem::take(&mut state.dropped_unqueued),
+            ));
+            if !already_committed {
+                state.flushes.push(PendingFlush {
+                    from,
+                    target,
+                    lost: lost.clone(),
+                });
+                state.flush_requested = true;
+            }
+            (target, lost)
+        };
+        self.not_empty.notify_one();
+        committed
+            .wait_for(|committed| *committed >= target)
+            .await
+            .map_err(|_| Error::WriterStopped)?;
+        if lost.load(Ordering::SeqCst) {
+            Err(Error::LogsNotWritten)
+        } else {
+            Ok(())
+        }
+    }
+
+    ///Waits for a batch of up to batch_size logs, lingering for at most flush_interval after the
+    ///first log arrives to give the batch a chance to fill.
+    ///Returns an empty batch and closes the queue once stop has been set and the queue is drained.
+    pub async fn next_batch(
+        &self,
+        batch_size: usize,
+        flush_interval: Duration,
+        stop: &AtomicBool,
+        stop_notify: &Notify,
+    ) -> Vec<(u64, NewLogModel)> {
+        let mut deadline: Option<Instant> = None;
+        loop {
+            let mut stop_notified = pin!(stop_notify.notified());
+            stop_notified.as_mut().enable();
+            let stopping = stop.load(Ordering::SeqCst);
+            {
+                let mut state = self.state.lock();
+                if !state.pending.is_empty() {
+                    if stopping || state.flush_requested || state.pending.len() >= batch_size {
+                        break;
+                    }
+                    if deadline.is_none() {
+                        deadline = Some(Instant::now() + flush_interval);
+                    }
+                } else if stopping {
+                    state.closed = true;
+                    break;
+                }
+            }
+            match deadline {
+                Some(deadline) => tokio::select! {
+                    _ = self.not_empty.notified() => {},
+                    _ = stop_notified => {},
+                    _ = tokio::time::sleep_until(deadline) => break,
+                },
+                None => tokio::select! {
+                    _ = self.not_empty.notified() => {},
+                    _ = stop_notified => {},
+                },
+            }
+        }
+        let mut state = self.state.lock();
+        let batch_len = state.pending.len().min(batch_size.max(1));
+        let batch: Vec<(u64, NewLogModel)> = state.pending.drain(..batch_len).collect();
+        if state.pending.is_empty() {
+            state.flush_requested = false;
+        }
+        drop(state);
+        self.not_full.notify_all();
+        batch
+    }
+
+    ///Records that the batch from first to last couldn't be written, must precede mark_committed
+    pub fn mark_failed(&self, first: u64, last: u64) {
+        self.state.lock().lose(first, last);
+    }
+
+    pub fn mark_committed(&self, sequence: u64) {
+        self.state
+            .lock()
+            .flushes
+            .retain(|flush| flush.target > sequence);
+        self.committed.send_if_modified(|committed| {
+            if sequence > *committed {
+                *committed = sequence;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    ///Rejects any further logs and wakes callers blocked on a full queue
+    pub fn close(&self) {
+        let mut state = self.state.lock();
+        state.closed = true;
+        let last_queued = state.last_queued;
+        if let Some((first, _)) = state.pending.front() {
+            let first = *first;
+            state.lose(first, last_queued);
+        }
+        drop(state);
+        self.not_full.notify_all();
+        //Nothing queued from here on will be written, don't leave flushes waiting on it
+        self.mark_committed(last_queued);
+    }
+}
+
+///Inserts the batch in a single transaction and returns the written logs with their assigned ids,
+///skipping logs with an ingest_id that is already stored
+pub(crate) fn write_batch(
+    connection: &mut SqliteConnection,
+    batch: &[NewLogModel],
+) -> Result<Vec<LogModel>, Error> {
+    connection
+        .transaction(|connection| {
+            //Diesel can't combine a multi-row insert with RETURNING on SQLite,
+            //one statement per log is cheap inside the transaction
+            batch
+                .iter()
+                .filter_map(|log| {
+                    diesel::insert_into(log_table::table)
+                        .values(log)
+                        .on_conflict_do_nothing()
+                        .returning(log_table::all_columns)
+                        .get_result(connection)
+                        .optional()
+                        .transpose()
+                })
+                .collect()
+        })
+        .map_err(|err| Error::DieselResult(DieselResultError(err)))
+}
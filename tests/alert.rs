@@ -0,0 +1,187 @@
+use std::{env, sync::Arc, time::Duration};
+
+use log_manager::{
+    alert::{Alert, AlertAction, AlertRule},
+    error::{BuilderError, Error},
+    logs::{Level, SimpleLog},
+    manager::{Builder, LogManager},
+    search::SearchFilter,
+};
+use parking_lot::Mutex;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum TestSource {
+    Server,
+    Agent(usize),
+}
+
+type Alerts = Arc<Mutex<Vec<Alert<TestSource>>>>;
+
+///Manager evaluating rule, with the alerts it fired
+async fn build(rule: AlertRule<TestSource>) -> (Arc<LogManager<TestSource>>, Alerts) {
+    let alerts: Alerts = Arc::new(Mutex::new(Vec::new()));
+    let alerts_ = alerts.to_owned();
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .alert_rule(rule.action(AlertAction::callback(move |alert| {
+            alerts_.lock().push(alert)
+        })))
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    (log_manager, alerts)
+}
+
+fn save(log_manager: &LogManager<TestSource>, level: Level, content: &str, source: TestSource) {
+    let log = SimpleLog::generate_log(level, "tests/alert".into(), content.into());
+    log_manager.save_logs([(log, source)]).unwrap()[0]
+        .as_ref()
+        .unwrap();
+}
+
+///Waits for count alerts to have been dispatched, returning the content and count of each.
+///Actions run in the order rules fired, so waiting for one also rules out any before it.
+async fn wait_for(alerts: &Alerts, count: usize) -> Vec<(String, usize)> {
+    for _ in 0..200 {
+        if alerts.lock().len() >= count {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    alerts
+        .lock()
+        .iter()
+        .map(|alert| (alert.content.to_owned(), alert.count))
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn threshold_counts_logs_within_the_window() {
+    let rule = AlertRule::new("errors", &SearchFilter::default().min_level(Level::Error))
+        .unwrap()
+        .threshold(2, Duration::from_millis(300))
+        .cooldown(Duration::ZERO);
+    let (log_manager, alerts) = build(rule).await;
+    save(&log_manager, Level::Error, "1", TestSource::Server);
+    save(&log_manager, Level::Info, "ignored", TestSource::Server);
+    save(&log_manager, Level::Error, "2", TestSource::Server);
+    save(&log_manager, Level::Error, "3", TestSource::Server);
+    assert_eq!(wait_for(&alerts, 1).await, [("3".into(), 3)]);
+    //The earlier logs have left the window
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    save(&log_manager, Level::Error, "4", TestSource::Server);
+    save(&log_manager, Level::Error, "5", TestSource::Server);
+    save(&log_manager, Level::Error, "6", TestSource::Server);
+    assert_eq!(
+        wait_for(&alerts, 2).await,
+        [("3".into(), 3), ("6".into(), 3)]
+    );
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cooldown_holds_back_alerts() {
+    let rule = AlertRule::new("any", &SearchFilter::default())
+        .unwrap()
+        .cooldown(Duration::from_millis(300));
+    let (log_manager, alerts) = build(rule).await;
+    save(&log_manager, Level::Info, "1", TestSource::Server);
+    save(&log_manager, Level::Info, "2", TestSource::Server);
+    assert_eq!(wait_for(&alerts, 1).await, [("1".into(), 1)]);
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    save(&log_manager, Level::Info, "3", TestSource::Server);
+    assert_eq!(
+        wait_for(&alerts, 2).await,
+        [("1".into(), 1), ("3".into(), 1)]
+    );
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn per_source_rules_count_each_source_separately() {
+    let rule = AlertRule::new("agents", &SearchFilter::default().source_prefix(&["Agent"]))
+        .unwrap()
+        .threshold(1, Duration::from_secs(60))
+        .per_source();
+    let (log_manager, alerts) = build(rule).await;
+    save(&log_manager, Level::Info, "1", TestSource::Agent(1));
+    save(&log_manager, Level::Info, "2", TestSource::Agent(2));
+    save(&log_manager, Level::Info, "server", TestSource::Server);
+    save(&log_manager, Level::Info, "3", TestSource::Agent(1));
+    //Each source has its own cool-down
+    save(&log_manager, Level::Info, "4", TestSource::Agent(1));
+    save(&log_manager, Level::Info, "5", TestSource::Agent(2));
+    assert_eq!(
+        wait_for(&alerts, 2).await,
+        [("3".into(), 2), ("5".into(), 2)]
+    );
+    let sources: Vec<TestSource> = alerts.lock().iter().map(|alert| alert.source).collect();
+    assert_eq!(sources, [TestSource::Agent(1), TestSource::Agent(2)]);
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn regex_has_to_match_the_content() {
+    let rule = AlertRule::new("slow", &SearchFilter::default().min_level(Level::Warn))
+        .unwrap()
+        .regex(Regex::new(r"took \d+ms").unwrap())
+        .cooldown(Duration::ZERO);
+    let (log_manager, alerts) = build(rule).await;
+    save(&log_manager, Level::Warn, "took long", TestSource::Server);
+    save(&log_manager, Level::Info, "took 5ms", TestSource::Server);
+    save(
+        &log_manager,
+        Level::Warn,
+        "request took 900ms",
+        TestSource::Server,
+    );
+    assert_eq!(
+        wait_for(&alerts, 1).await,
+        [("request took 900ms".into(), 1)]
+    );
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn file_action_appends_json_lines() {
+    let path = env::temp_dir().join(format!("log-manager-alerts-{}.jsonl", Uuid::new_v4()));
+    let rule = AlertRule::new("errors", &SearchFilter::default().min_level(Level::Error))
+        .unwrap()
+        .cooldown(Duration::ZERO)
+        .action(AlertAction::File(path.to_owned()));
+    //The callback runs after the file action, so the line is written once it has been called
+    let (log_manager, alerts) = build(rule).await;
+    save(&log_manager, Level::Error, "first", TestSource::Agent(1));
+    save(&log_manager, Level::Error, "second", TestSource::Server);
+    wait_for(&alerts, 2).await;
+    let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["rule"], "errors");
+    assert_eq!(lines[0]["content"], "first");
+    //Sources are embedded as JSON rather than as the string they are stored as
+    assert_eq!(lines[0]["source"], serde_json::json!({"Agent": 1}));
+    assert_eq!(lines[1]["source"], "Server");
+    log_manager.stop();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rule_for_another_source_type_is_rejected() {
+    let rule = AlertRule::<String>::new("any", &SearchFilter::default()).unwrap();
+    let result = Builder::default()
+        .database_url(":memory:".into())
+        .alert_rule(rule)
+        .build::<TestSource>()
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::Builder(BuilderError::SourceTypeMismatch(_)))
+    ));
+}
@@ -0,0 +1,197 @@
+use std::{
+    env,
+    net::{SocketAddr, TcpListener},
+    time::Duration,
+};
+
+use log_manager::{
+    client,
+    error::Error,
+    ingest::{IngestBatch, IngestLog},
+    logs::{Level, SimpleLog},
+    manager::{Builder, Pagination},
+    search::{SearchFilter, Sort, SortDirection, SortField},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const LOGS: usize = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum TestSource {
+    Agent(usize),
+}
+
+///Source type of a server which doesn't know about agents yet
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum OlderSource {
+    Server,
+}
+
+fn free_address() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+fn temp_database() -> String {
+    env::temp_dir()
+        .join(format!("log-manager-{}.sqlite", Uuid::new_v4()))
+        .to_string_lossy()
+        .to_string()
+}
+
+fn remove_database(database_url: &str) {
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{database_url}{suffix}"));
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_buffers_until_server_is_reachable() {
+    let address = free_address();
+    let server_url = format!("http://{address}");
+    let buffer_url = temp_database();
+    let database_url = temp_database();
+    let build_client = || {
+        client::Builder::default()
+            .server_url(server_url.to_owned())
+            .buffer_url(buffer_url.to_owned())
+            .batch_size(7)
+            .retry_interval(Duration::from_millis(50))
+            .build::<TestSource>()
+    };
+
+    let log_client = build_client().await.unwrap();
+    for i in 0..LOGS {
+        log_client
+            .save_log(
+                SimpleLog::generate_log(Level::Info, "tests/client".into(), i.to_string()),
+                TestSource::Agent(i % 3 / 2),
+            )
+            .unwrap();
+    }
+    assert!(log_client.flush().await.is_err());
+    assert_eq!(log_client.pending().unwrap() as usize, LOGS);
+    log_client.stop();
+
+    //The buffer outlives the client
+    let log_client = build_client().await.unwrap();
+    assert_eq!(log_client.pending().unwrap() as usize, LOGS);
+
+    let log_manager = Builder::default()
+        .database_url(database_url.to_owned())
+        .http_address(address)
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    log_client.flush().await.unwrap();
+    assert_eq!(log_client.pending().unwrap(), 0);
+
+    let results = log_manager
+        .search(
+            &SearchFilter::default(),
+            Sort::new(SortField::Id, SortDirection::Ascending),
+            Some(Pagination::Page {
+                page: 1,
+                page_size: LOGS + 1,
+            }),
+        )
+        .unwrap();
+    assert_eq!(results.total_count as usize, LOGS);
+    let contents: Vec<String> = results
+        .logs
+        .into_iter()
+        .map(|log| log.into_simple_log().content)
+        .collect();
+    let expected: Vec<String> = (0..LOGS).map(|i| i.to_string()).collect();
+    assert_eq!(contents, expected);
+
+    //Repeating a request doesn't store its logs twice
+    let batch = IngestBatch {
+        source: TestSource::Agent(0),
+        logs: vec![IngestLog {
+            log: SimpleLog::generate_log(Level::Info, "tests/client".into(), "retried".into()),
+            id: Some(Uuid::new_v4().to_string()),
+        }],
+    };
+    let http = reqwest::Client::new();
+    for _ in 0..2 {
+        let response = http
+            .post(format!("{server_url}/logs"))
+            .json(&batch)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+    }
+    let results = log_manager
+        .search(
+            &SearchFilter::default().content("retried"),
+            Sort::default(),
+            None,
+        )
+        .unwrap();
+    assert_eq!(results.total_count, 1);
+
+    log_client.stop();
+    log_manager.stop();
+    remove_database(&buffer_url);
+    remove_database(&database_url);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejected_logs_are_kept_until_a_server_accepts_them() {
+    let older_address = free_address();
+    let address = free_address();
+    let buffer_url = temp_database();
+    let build_client = |address: SocketAddr| {
+        client::Builder::default()
+            .server_url(format!("http://{address}"))
+            .buffer_url(buffer_url.to_owned())
+            .retry_interval(Duration::from_millis(50))
+            .build::<TestSource>()
+    };
+    let older_manager = Builder::default()
+        .database_url(":memory:".into())
+        .http_address(older_address)
+        .build::<OlderSource>()
+        .await
+        .unwrap();
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .http_address(address)
+        .build::<TestSource>()
+        .await
+        .unwrap();
+
+    let log_client = build_client(older_address).await.unwrap();
+    for i in 0..LOGS {
+        log_client
+            .save_log(
+                SimpleLog::generate_log(Level::Info, "tests/client".into(), i.to_string()),
+                TestSource::Agent(i),
+            )
+            .unwrap();
+    }
+    assert!(matches!(
+        log_client.flush().await,
+        Err(Error::ServerResponse(422, _))
+    ));
+    assert_eq!(log_client.pending().unwrap() as usize, LOGS);
+    log_client.stop();
+
+    let log_client = build_client(address).await.unwrap();
+    log_client.flush().await.unwrap();
+    assert_eq!(log_client.pending().unwrap(), 0);
+    let results = log_manager
+        .search(&SearchFilter::default(), Sort::default(), None)
+        .unwrap();
+    assert_eq!(results.total_count as usize, LOGS);
+
+    log_client.stop();
+    older_manager.stop();
+    log_manager.stop();
+    remove_database(&buffer_url);
+}
@@ -0,0 +1,202 @@
+use std::{collections::BTreeSet, ops::Bound, time::Duration};
+
+use chrono::{DateTime, Utc};
+use log_manager::{
+    logs::{FieldValue, Fields, Level, Log, SimpleLog, SpanContext},
+    manager::Builder,
+    search::{SearchFilter, Sort},
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum TestSource {
+    Server,
+    Agent(usize),
+}
+
+fn id(log: &Log<TestSource>) -> i64 {
+    i64::from(log.id())
+}
+
+fn timestamp(minute: u32) -> String {
+    format!("2026-10-17T12:{minute:02}:00Z")
+}
+
+fn log(minute: u32, level: Level, content: &str) -> SimpleLog {
+    let mut log = SimpleLog::generate_log(level, "tests/fields".into(), content.into());
+    log.timestamp = timestamp(minute);
+    log
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn non_finite_floats_are_stored_as_strings() {
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    let saved = log(0, Level::Info, "ratios")
+        .field("nan", f64::NAN)
+        .field("infinity", FieldValue::F64(f64::INFINITY))
+        .field("negative_infinity", f64::NEG_INFINITY)
+        .field("finite", 1.5);
+    for result in log_manager
+        .save_logs([(saved, TestSource::Server)])
+        .unwrap()
+    {
+        result.unwrap();
+    }
+
+    let results = log_manager
+        .search(&SearchFilter::default(), Sort::default(), None)
+        .unwrap();
+    assert!(results.failed.is_empty());
+    assert_eq!(results.logs.len(), 1);
+    let fields = results
+        .logs
+        .into_iter()
+        .next()
+        .unwrap()
+        .into_simple_log()
+        .fields;
+    let expected: Fields = [
+        ("nan", FieldValue::String("NaN".into())),
+        ("infinity", FieldValue::String("inf".into())),
+        ("negative_infinity", FieldValue::String("-inf".into())),
+        ("finite", FieldValue::F64(1.5)),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value))
+    .collect();
+    assert_eq!(fields, expected);
+
+    let nan = log_manager
+        .search(
+            &SearchFilter::default().field("nan", f64::NAN),
+            Sort::default(),
+            None,
+        )
+        .unwrap();
+    assert_eq!(nan.total_count, 1);
+    log_manager.stop();
+}
+
+///Subscriptions evaluate filters in memory, they have to agree with the SQL search uses
+#[tokio::test(flavor = "multi_thread")]
+async fn subscriptions_match_the_same_logs_as_search() {
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    let minute = |minute: u32| {
+        DateTime::parse_from_rfc3339(&timestamp(minute))
+            .unwrap()
+            .with_timezone(&Utc)
+    };
+    let filters = vec![
+        SearchFilter::default(),
+        SearchFilter::default().source(TestSource::Server),
+        SearchFilter::default().source_prefix(&["Agent"]),
+        SearchFilter::default().source_prefix(&["Agent", "1"]),
+        SearchFilter::default().levels(&[Level::Debug, Level::Error]),
+        SearchFilter::default().min_level(Level::Warn),
+        SearchFilter::default().content("DISK"),
+        SearchFilter::default()
+            .from(Bound::Included(minute(2)))
+            .to(Bound::Excluded(minute(5))),
+        SearchFilter::default().field("status", 500),
+        SearchFilter::default().field("status", 500.0),
+        SearchFilter::default().field_range(
+            "latency",
+            Bound::Included(10.into()),
+            Bound::Excluded(100.5.into()),
+        ),
+        SearchFilter::default().field_range(
+            "latency",
+            Bound::Unbounded,
+            Bound::Included(10.into()),
+        ),
+        SearchFilter::default().field("flag", true),
+        SearchFilter::default().field("name", "1"),
+        SearchFilter::default().field("name", 1),
+        SearchFilter::default().field("ratio", f64::NAN),
+        SearchFilter::default().field("missing", 1),
+        SearchFilter::default().trace_id("trace-1"),
+        SearchFilter::default()
+            .source_prefix(&["Agent"])
+            .min_level(Level::Info)
+            .field_range("latency", Bound::Excluded(5.into()), Bound::Unbounded),
+    ];
+    let mut subscriptions: Vec<_> = filters
+        .iter()
+        .map(|filter| log_manager.subscribe(filter).unwrap())
+        .collect();
+
+    let logs = vec![
+        (
+            log(0, Level::Info, "Disk almost full")
+                .field("status", 500)
+                .field("latency", 10),
+            TestSource::Server,
+        ),
+        (
+            log(1, Level::Error, "request failed")
+                .field("status", 200)
+                .field("latency", 100.5)
+                .field("flag", true)
+                .span(SpanContext::new("trace-1")),
+            TestSource::Agent(1),
+        ),
+        (
+            log(2, Level::Debug, "disk ok")
+                .field("latency", 99.9)
+                .field("name", "1")
+                .field("ratio", f64::NAN),
+            TestSource::Agent(1),
+        ),
+        (
+            log(3, Level::Warn, "slow")
+                .field("latency", 5)
+                .field("name", 1)
+                .field("flag", false),
+            TestSource::Agent(12),
+        ),
+        (
+            log(4, Level::Trace, "tick")
+                .field("status", 500.0)
+                .span(SpanContext::new("trace-2")),
+            TestSource::Agent(2),
+        ),
+        (log(5, Level::Info, "no fields"), TestSource::Server),
+        (
+            log(6, Level::Info, "huge")
+                .field("latency", u64::MAX)
+                .field("status", -500),
+            TestSource::Agent(1),
+        ),
+    ];
+    for result in log_manager.save_logs(logs).unwrap() {
+        result.unwrap();
+    }
+
+    for (index, (filter, subscription)) in filters.iter().zip(subscriptions.iter_mut()).enumerate()
+    {
+        let searched: BTreeSet<i64> = log_manager
+            .search(filter, Sort::default(), None)
+            .unwrap()
+            .logs
+            .iter()
+            .map(id)
+            .collect();
+        let mut received = BTreeSet::new();
+        //Everything was published before save_logs returned
+        while let Ok(log) =
+            tokio::time::timeout(Duration::from_millis(50), subscription.recv()).await
+        {
+            received.insert(id(&log.unwrap()));
+        }
+        assert_eq!(received, searched, "filter {index}");
+    }
+    log_manager.stop();
+}
@@ -0,0 +1,180 @@
+use std::env;
+
+use diesel::{connection::SimpleConnection, Connection, SqliteConnection};
+use log_manager::{
+    logs::{Level, SimpleLog},
+    manager::{Builder, LogManager},
+    search::{Highlight, SearchFilter, SearchResults, Sort, SortDirection, SortField},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum TestSource {
+    Server,
+}
+
+const CONTENTS: [&str; 4] = [
+    "connection reset by peer",
+    "agent timeout while connecting",
+    "connection established to agent",
+    "disk full",
+];
+
+fn temp_database() -> String {
+    env::temp_dir()
+        .join(format!("log-manager-{}.sqlite", Uuid::new_v4()))
+        .to_string_lossy()
+        .to_string()
+}
+
+fn remove_database(database_url: &str) {
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{database_url}{suffix}"));
+    }
+}
+
+fn save(log_manager: &LogManager<TestSource>, contents: &[&str]) {
+    let logs = contents.iter().map(|content| {
+        (
+            SimpleLog::generate_log(Level::Info, "tests/full_text".into(), content.to_string()),
+            TestSource::Server,
+        )
+    });
+    for result in log_manager.save_logs(logs).unwrap() {
+        result.unwrap();
+    }
+}
+
+fn search(
+    log_manager: &LogManager<TestSource>,
+    filter: &SearchFilter<TestSource>,
+) -> SearchResults<TestSource> {
+    log_manager
+        .search(
+            filter,
+            Sort::new(SortField::Id, SortDirection::Ascending),
+            None,
+        )
+        .unwrap()
+}
+
+fn matches(log_manager: &LogManager<TestSource>, query: &str) -> Vec<String> {
+    search(log_manager, &SearchFilter::default().full_text(query))
+        .logs
+        .into_iter()
+        .map(|log| log.into_simple_log().content)
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn phrase_boolean_and_prefix_queries() {
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    save(&log_manager, &CONTENTS);
+
+    assert_eq!(matches(&log_manager, "\"connection reset\""), [CONTENTS[0]]);
+    //The words of a phrase have to be adjacent and in order
+    assert!(matches(&log_manager, "\"reset connection\"").is_empty());
+    assert_eq!(matches(&log_manager, "connection AND agent"), [CONTENTS[2]]);
+    assert_eq!(
+        matches(&log_manager, "reset OR disk"),
+        [CONTENTS[0], CONTENTS[3]]
+    );
+    assert_eq!(matches(&log_manager, "connection NOT agent"), [CONTENTS[0]]);
+    assert_eq!(
+        matches(&log_manager, "connect*"),
+        [CONTENTS[0], CONTENTS[1], CONTENTS[2]]
+    );
+    //Tokens are matched whole without a prefix query
+    assert!(matches(&log_manager, "connect").is_empty());
+    //Combined with the other conditions of the filter
+    let results = search(
+        &log_manager,
+        &SearchFilter::default()
+            .full_text("connect*")
+            .content("agent"),
+    );
+    assert_eq!(results.total_count, 2);
+
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn snippets_highlight_the_matches() {
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    save(&log_manager, &CONTENTS);
+
+    let results = search(
+        &log_manager,
+        &SearchFilter::default()
+            .full_text("connection")
+            .highlight(Highlight::default()),
+    );
+    assert_eq!(
+        results.snippets.unwrap(),
+        [
+            "[connection] reset by peer",
+            "[connection] established to agent"
+        ]
+    );
+
+    let highlight = Highlight {
+        open: "<b>".into(),
+        close: "</b>".into(),
+        ellipsis: "…".into(),
+        max_tokens: 2,
+    };
+    let results = search(
+        &log_manager,
+        &SearchFilter::default()
+            .full_text("peer")
+            .highlight(highlight),
+    );
+    assert_eq!(results.snippets.unwrap(), ["…by <b>peer</b>"]);
+
+    //Only requested along with a full-text query
+    let results = search(&log_manager, &SearchFilter::default().content("connection"));
+    assert!(results.snippets.is_none());
+
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn index_follows_updates_to_the_content() {
+    let database_url = temp_database();
+    let log_manager = Builder::default()
+        .database_url(database_url.to_owned())
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    save(&log_manager, &CONTENTS);
+
+    let mut connection = SqliteConnection::establish(&database_url).unwrap();
+    connection
+        .batch_execute(
+            "UPDATE log SET content = 'disk replaced' WHERE content = 'disk full';
+            UPDATE log SET level = 4 WHERE content = 'connection reset by peer';",
+        )
+        .unwrap();
+
+    assert_eq!(matches(&log_manager, "replaced"), ["disk replaced"]);
+    assert!(matches(&log_manager, "full").is_empty());
+    //Updating other columns leaves the index alone
+    assert_eq!(matches(&log_manager, "reset"), [CONTENTS[0]]);
+    //Fails if the index no longer matches the content it was built from
+    connection
+        .batch_execute("INSERT INTO log_fts (log_fts, rank) VALUES ('integrity-check', 1);")
+        .unwrap();
+
+    log_manager.stop();
+    drop(connection);
+    remove_database(&database_url);
+}
@@ -0,0 +1,183 @@
+use std::{sync::Arc, time::Duration};
+
+use log_manager::{
+    aggregate::GroupBy,
+    error::{BuilderError, Error},
+    heartbeat::{HeartbeatEvent, HeartbeatMonitor, HeartbeatStatus, HEARTBEAT_LOCATION},
+    logs::{Level, SimpleLog},
+    manager::{Builder, LogManager},
+    search::{SearchFilter, Sort, SortDirection, SortField},
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum TestSource {
+    Server,
+    Agent(usize),
+}
+
+const WINDOW: Duration = Duration::from_millis(500);
+
+///Waits for the monitor to have reported count events, returning the first count
+async fn wait_for_events(
+    events: &Mutex<Vec<HeartbeatEvent<TestSource>>>,
+    count: usize,
+) -> Vec<(TestSource, HeartbeatStatus)> {
+    for _ in 0..200 {
+        if events.lock().len() >= count {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    events
+        .lock()
+        .iter()
+        .take(count)
+        .map(|event| (event.source, event.status))
+        .collect()
+}
+
+fn heartbeat_logs(log_manager: &LogManager<TestSource>) -> Vec<(Level, String)> {
+    log_manager
+        .search(
+            &SearchFilter::default().heartbeat(true),
+            Sort::new(SortField::Id, SortDirection::Ascending),
+            None,
+        )
+        .unwrap()
+        .logs
+        .into_iter()
+        .map(|log| {
+            let log = log.into_simple_log();
+            assert_eq!(log.location, HEARTBEAT_LOCATION);
+            (log.level, log.content)
+        })
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sources_go_silent_and_recover() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let events_ = events.to_owned();
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .flush_interval(Duration::from_millis(10))
+        .heartbeat(
+            HeartbeatMonitor::new(WINDOW)
+                .check_interval(Duration::from_millis(20))
+                .expect(TestSource::Agent(1))
+                .callback(move |event| events_.lock().push(event)),
+        )
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    //Not expected and not tracked, never reported
+    log_manager
+        .save_log(
+            SimpleLog::generate_log(Level::Info, "tests/heartbeat".into(), "unwatched".into()),
+            TestSource::Server,
+        )
+        .unwrap();
+
+    let reported = wait_for_events(&events, 1).await;
+    assert_eq!(reported, [(TestSource::Agent(1), HeartbeatStatus::Silent)]);
+    assert_eq!(events.lock()[0].last_seen, None);
+
+    //A log at the monitor's location from the source itself still counts as activity
+    log_manager
+        .save_log(
+            SimpleLog::generate_log(Level::Info, HEARTBEAT_LOCATION.into(), "back".into()),
+            TestSource::Agent(1),
+        )
+        .unwrap();
+    let reported = wait_for_events(&events, 2).await;
+    assert_eq!(
+        reported,
+        [
+            (TestSource::Agent(1), HeartbeatStatus::Silent),
+            (TestSource::Agent(1), HeartbeatStatus::Recovered)
+        ]
+    );
+    assert!(events.lock()[1].last_seen.is_some());
+    //The callback runs before the log is queued
+    let mut logs = Vec::new();
+    for _ in 0..200 {
+        log_manager.flush().await.unwrap();
+        logs = heartbeat_logs(&log_manager);
+        if logs.len() >= 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    //Nothing more is written once stopped, so the counts below can't change in between
+    log_manager.stop();
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[0].0, Level::Error);
+    assert!(logs[0].1.contains("last seen never"));
+    assert_eq!(logs[1].0, Level::Info);
+
+    //The monitor's logs are only left out when asked for
+    let without_heartbeats = SearchFilter::default().heartbeat(false);
+    let results = log_manager
+        .search(&without_heartbeats, Sort::default(), None)
+        .unwrap();
+    assert_eq!(results.total_count, 2);
+    for filter in [SearchFilter::default(), without_heartbeats] {
+        let total_count = log_manager
+            .search(&filter, Sort::default(), None)
+            .unwrap()
+            .total_count;
+        let aggregated = log_manager.aggregate(&filter, GroupBy::default()).unwrap();
+        assert_eq!(aggregated[0].count, total_count);
+    }
+    let agent = log_manager
+        .sources()
+        .unwrap()
+        .into_iter()
+        .find(|summary| summary.source == TestSource::Agent(1))
+        .unwrap();
+    assert_eq!(agent.count, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn emitting_logs_can_be_turned_off() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let events_ = events.to_owned();
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .heartbeat(
+            HeartbeatMonitor::new(WINDOW)
+                .check_interval(Duration::from_millis(20))
+                .track_seen_sources(true)
+                .emit_logs(false)
+                .callback(move |event| events_.lock().push(event)),
+        )
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    log_manager
+        .save_logs([(
+            SimpleLog::generate_log(Level::Info, "tests/heartbeat".into(), "seen".into()),
+            TestSource::Agent(2),
+        )])
+        .unwrap();
+    let reported = wait_for_events(&events, 1).await;
+    assert_eq!(reported, [(TestSource::Agent(2), HeartbeatStatus::Silent)]);
+    log_manager.flush().await.unwrap();
+    assert!(heartbeat_logs(&log_manager).is_empty());
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn monitor_for_another_source_type_is_rejected() {
+    let result = Builder::default()
+        .database_url(":memory:".into())
+        .heartbeat(HeartbeatMonitor::<String>::new(WINDOW))
+        .build::<TestSource>()
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::Builder(BuilderError::SourceTypeMismatch(_)))
+    ));
+}
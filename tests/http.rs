@@ -0,0 +1,224 @@
+use std::{net::TcpListener, sync::Arc};
+
+use log_manager::{
+    http::MAX_LIMIT,
+    logs::{Level, SimpleLog, SpanContext},
+    manager::{Builder, LogManager},
+    search::SearchResults,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum TestSource {
+    Server,
+    Agent(usize),
+}
+
+///Manager serving the API on a free port, with the base url of the API
+async fn serve() -> (Arc<LogManager<TestSource>>, String) {
+    let address = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .http_address(address)
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    (log_manager, format!("http://{address}"))
+}
+
+fn log(level: Level, content: &str) -> SimpleLog {
+    SimpleLog::generate_log(level, "tests/http".into(), content.into())
+}
+
+async fn get(url: &str, query: &[(&str, &str)]) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(url)
+        .query(query)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn search(server_url: &str, query: &[(&str, &str)]) -> SearchResults<TestSource> {
+    let response = get(&format!("{server_url}/logs"), query).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    response.json().await.unwrap()
+}
+
+fn contents(results: SearchResults<TestSource>) -> Vec<String> {
+    results
+        .logs
+        .into_iter()
+        .map(|log| log.into_simple_log().content)
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_logs_filters_and_paginates() {
+    let (log_manager, server_url) = serve().await;
+    log_manager
+        .save_logs([
+            (log(Level::Info, "0"), TestSource::Server),
+            (log(Level::Warn, "1"), TestSource::Agent(1)),
+            (log(Level::Error, "2"), TestSource::Agent(1)),
+            (log(Level::Warn, "3"), TestSource::Agent(2)),
+            (log(Level::Debug, "4"), TestSource::Agent(1)),
+        ])
+        .unwrap();
+    let ascending = [("sort", "Id"), ("direction", "Ascending")];
+
+    let results = search(
+        &server_url,
+        &[ascending.as_slice(), &[("levels", "warn,error")]].concat(),
+    )
+    .await;
+    assert_eq!(results.total_count, 3);
+    assert_eq!(contents(results), ["1", "2", "3"]);
+    let results = search(
+        &server_url,
+        &[ascending.as_slice(), &[("source", r#"{"Agent":1}"#)]].concat(),
+    )
+    .await;
+    assert_eq!(contents(results), ["1", "2", "4"]);
+    let results = search(
+        &server_url,
+        &[ascending.as_slice(), &[("source_prefix", r#"["Agent"]"#)]].concat(),
+    )
+    .await;
+    assert_eq!(contents(results), ["1", "2", "3", "4"]);
+    let results = search(
+        &server_url,
+        &[
+            ascending.as_slice(),
+            &[("full_text", "2 OR 3"), ("highlight", "true")],
+        ]
+        .concat(),
+    )
+    .await;
+    assert_eq!(
+        results.snippets.as_deref(),
+        Some(["[2]", "[3]"].map(String::from).as_slice())
+    );
+    //Newest first by default
+    assert_eq!(
+        contents(search(&server_url, &[]).await),
+        ["4", "3", "2", "1", "0"]
+    );
+
+    let results = search(
+        &server_url,
+        &[ascending.as_slice(), &[("page", "2"), ("page_size", "2")]].concat(),
+    )
+    .await;
+    assert_eq!(results.total_count, 5);
+    assert_eq!(contents(results), ["2", "3"]);
+
+    let mut walked = Vec::new();
+    let mut after = String::new();
+    for _ in 0..5 {
+        let results = search(
+            &server_url,
+            &[ascending.as_slice(), &[("limit", "2"), ("after", &after)]].concat(),
+        )
+        .await;
+        let next_cursor = results.next_cursor;
+        walked.extend(contents(results));
+        match next_cursor {
+            Some(cursor) => after = cursor.to_string(),
+            None => break,
+        }
+    }
+    assert_eq!(walked, ["0", "1", "2", "3", "4"]);
+
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_logs_caps_the_limit() {
+    let (log_manager, server_url) = serve().await;
+    let logs = (0..=MAX_LIMIT).map(|i| (log(Level::Info, &i.to_string()), TestSource::Server));
+    log_manager.save_logs(logs).unwrap();
+    let limit = usize::MAX.to_string();
+    for parameter in ["limit", "page_size"] {
+        let results = search(&server_url, &[(parameter, &limit)]).await;
+        assert_eq!(results.total_count as usize, MAX_LIMIT + 1);
+        assert_eq!(results.logs.len(), MAX_LIMIT);
+    }
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invalid_requests_are_bad_requests() {
+    let (log_manager, server_url) = serve().await;
+    log_manager
+        .save_logs([(log(Level::Info, "saved"), TestSource::Server)])
+        .unwrap();
+    let page = usize::MAX.to_string();
+    for query in [
+        [("full_text", "\"unterminated")],
+        [("full_text", "AND")],
+        [("full_text", "saved OR (")],
+        [("levels", "loud")],
+        [("after", "not a cursor")],
+        [("source", "Server")],
+        [("from", "yesterday")],
+        [("page", page.as_str())],
+    ] {
+        let response = get(&format!("{server_url}/logs"), &query).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{query:?}");
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert!(body["error"].is_string());
+    }
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_trace_returns_the_logs_of_one_trace() {
+    let (log_manager, server_url) = serve().await;
+    log_manager
+        .save_logs([
+            (
+                log(Level::Info, "received").span(SpanContext::new("t1")),
+                TestSource::Server,
+            ),
+            (
+                log(Level::Info, "other").span(SpanContext::new("t2")),
+                TestSource::Server,
+            ),
+            (log(Level::Info, "untraced"), TestSource::Agent(1)),
+            (
+                log(Level::Warn, "forwarded").span(SpanContext::new("t1")),
+                TestSource::Agent(1),
+            ),
+        ])
+        .unwrap();
+    let response = get(&format!("{server_url}/traces/t1"), &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let results: SearchResults<TestSource> = response.json().await.unwrap();
+    assert_eq!(results.total_count, 2);
+    assert!(results.failed.is_empty());
+    let logs: Vec<_> = results
+        .logs
+        .into_iter()
+        .map(|log| {
+            let log = log.into_simple_log();
+            (log.content, log.span.unwrap().trace_id)
+        })
+        .collect();
+    assert_eq!(
+        logs,
+        [
+            ("received".to_string(), "t1".to_string()),
+            ("forwarded".to_string(), "t1".to_string())
+        ]
+    );
+    let response = get(&format!("{server_url}/traces/unknown"), &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let results: SearchResults<TestSource> = response.json().await.unwrap();
+    assert!(results.logs.is_empty());
+    log_manager.stop();
+}
@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use log_manager::{
+    layer::LogManagerLayer,
+    logs::{FieldValue, Level, Log},
+    manager::{Builder, LogManager},
+    search::{SearchFilter, Sort, SortDirection, SortField},
+};
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::{layer::SubscriberExt, Registry};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum TestSource {
+    Server,
+}
+
+async fn build() -> Arc<LogManager<TestSource>> {
+    Builder::default()
+        .database_url(":memory:".into())
+        .build::<TestSource>()
+        .await
+        .unwrap()
+}
+
+///Runs f with the layer as the default subscriber and returns what was saved, oldest first
+async fn record(
+    log_manager: &Arc<LogManager<TestSource>>,
+    f: impl FnOnce(),
+) -> Vec<Log<TestSource>> {
+    let subscriber = Registry::default().with(LogManagerLayer::new(
+        log_manager.to_owned(),
+        TestSource::Server,
+    ));
+    tracing::subscriber::with_default(subscriber, f);
+    log_manager.flush().await.unwrap();
+    log_manager
+        .search(
+            &SearchFilter::default(),
+            Sort::new(SortField::Id, SortDirection::Ascending),
+            None,
+        )
+        .unwrap()
+        .logs
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn events_are_saved_with_their_location_content_and_fields() {
+    let log_manager = build().await;
+    let mut line = 0;
+    let logs = record(&log_manager, || {
+        line = line!() + 1;
+        tracing::warn!(status = 500, path = "/logs", "request failed");
+        tracing::info!(retries = 3);
+    })
+    .await;
+    let logs: Vec<_> = logs.into_iter().map(Log::into_simple_log).collect();
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[0].level, Level::Warn);
+    assert_eq!(logs[0].location, format!("tests/layer.rs:{line}"));
+    assert_eq!(logs[0].content, "request failed status=500 path=\"/logs\"");
+    assert_eq!(logs[0].fields.get("status"), Some(&FieldValue::I64(500)));
+    assert_eq!(
+        logs[0].fields.get("path"),
+        Some(&FieldValue::String("/logs".into()))
+    );
+    //Without a message the content is only the fields
+    assert_eq!(logs[1].content, "retries=3");
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn events_from_a_crate_named_log_manager_are_saved() {
+    let log_manager = build().await;
+    let logs = record(&log_manager, || {
+        tracing::info!(target: "log_manager", "from the binary");
+        tracing::info!(target: "log_manager::jobs", "from a module of the binary");
+    })
+    .await;
+    let contents: Vec<String> = logs
+        .into_iter()
+        .map(|log| log.into_simple_log().content)
+        .collect();
+    assert_eq!(contents, ["from the binary", "from a module of the binary"]);
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn events_are_saved_with_the_context_of_their_span() {
+    let log_manager = build().await;
+    let logs = record(&log_manager, || {
+        tracing::info!("outside");
+        tracing::info_span!("request", trace_id = "abc123").in_scope(|| {
+            tracing::info!("in request");
+            tracing::info_span!("query").in_scope(|| tracing::info!("in query"));
+        });
+        tracing::info_span!("job").in_scope(|| tracing::info!("in job"));
+    })
+    .await;
+    let spans: Vec<_> = logs
+        .into_iter()
+        .map(|log| log.into_simple_log().span)
+        .collect();
+    assert_eq!(spans.len(), 4);
+    assert!(spans[0].is_none());
+
+    let request = spans[1].to_owned().unwrap();
+    assert_eq!(request.trace_id, "abc123");
+    assert_eq!(request.span_name.as_deref(), Some("request"));
+    assert!(request.span_id.is_some());
+    assert_eq!(request.parent_span_id, None);
+    //Children share the trace of their parent and link to it
+    let query = spans[2].to_owned().unwrap();
+    assert_eq!(query.trace_id, "abc123");
+    assert_eq!(query.span_name.as_deref(), Some("query"));
+    assert_ne!(query.span_id, request.span_id);
+    assert_eq!(query.parent_span_id, request.span_id);
+    //Root spans without a trace id start a new trace
+    let job = spans[3].to_owned().unwrap();
+    assert_ne!(job.trace_id, "abc123");
+    assert_eq!(job.parent_span_id, None);
+    log_manager.stop();
+}
@@ -0,0 +1,157 @@
+use std::env;
+
+use chrono::DateTime;
+use diesel::{connection::SimpleConnection, Connection, SqliteConnection};
+use log_manager::{
+    logs::Level,
+    manager::{Builder, Pagination},
+    search::{SearchFilter, Sort, SortDirection, SortField},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const BASELINE_MIGRATION: &str = include_str!("../migrations/2023-10-29-073935_log_table/up.sql");
+const BASELINE_VERSION: &str = "20231029073935";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum TestSource {
+    Server,
+    Agent(usize),
+}
+
+fn temp_database() -> String {
+    env::temp_dir()
+        .join(format!("log-manager-{}.sqlite", Uuid::new_v4()))
+        .to_string_lossy()
+        .to_string()
+}
+
+fn remove_database(database_url: &str) {
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{database_url}{suffix}"));
+    }
+}
+
+///Rows as the baseline stored them, every column JSON encoded
+const BASELINE_ROWS: &str = r#"
+INSERT INTO log (id, source, timestamp, level, location, content) VALUES
+    (1, '"Server"', '"2023-10-29T07:39:35Z"', '"Info"', '"src/main.rs:10"', '"started"'),
+    (2, '{"Agent":3}', '"2023-10-29T07:39:35.123456Z"', '"Warn"', '"src/agent.rs:5"', '"slow \"reply\""'),
+    (3, '"Server"', '"2023-10-29T09:39:35.5+02:00"', '"Error"', '"src/main.rs:20"', '"failed"'),
+    (4, '{"Agent":4}', '"2023-10-29T02:09:35-05:30"', '"Trace"', '"src/agent.rs:9"', '"ping"'),
+    (5, '"Server"', '"2023-10-29T07:39:35.000001+00:00"', '"Debug"', '"src/main.rs:30"', '"done"');
+"#;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn baseline_database_is_migrated() {
+    let database_url = temp_database();
+    {
+        let mut connection = SqliteConnection::establish(&database_url).unwrap();
+        connection
+            .batch_execute(&format!(
+                "CREATE TABLE __diesel_schema_migrations (
+                    version VARCHAR(50) PRIMARY KEY NOT NULL,
+                    run_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+                );
+                INSERT INTO __diesel_schema_migrations (version) VALUES ('{BASELINE_VERSION}');
+                {BASELINE_MIGRATION}
+                {BASELINE_ROWS}"
+            ))
+            .unwrap();
+    }
+
+    let log_manager = Builder::default()
+        .database_url(database_url.to_owned())
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    let results = log_manager
+        .search(
+            &SearchFilter::default(),
+            Sort::new(SortField::Id, SortDirection::Ascending),
+            Some(Pagination::After {
+                cursor: None,
+                limit: 10,
+            }),
+        )
+        .unwrap();
+    assert!(results.failed.is_empty());
+    let expected = [
+        (
+            1,
+            TestSource::Server,
+            "2023-10-29T07:39:35Z",
+            Level::Info,
+            "src/main.rs:10",
+            "started",
+        ),
+        (
+            2,
+            TestSource::Agent(3),
+            "2023-10-29T07:39:35.123456Z",
+            Level::Warn,
+            "src/agent.rs:5",
+            "slow \"reply\"",
+        ),
+        (
+            3,
+            TestSource::Server,
+            "2023-10-29T07:39:35.5Z",
+            Level::Error,
+            "src/main.rs:20",
+            "failed",
+        ),
+        (
+            4,
+            TestSource::Agent(4),
+            "2023-10-29T07:39:35Z",
+            Level::Trace,
+            "src/agent.rs:9",
+            "ping",
+        ),
+        (
+            5,
+            TestSource::Server,
+            "2023-10-29T07:39:35.000001Z",
+            Level::Debug,
+            "src/main.rs:30",
+            "done",
+        ),
+    ];
+    assert_eq!(results.logs.len(), expected.len());
+    for (log, (id, source, timestamp, level, location, content)) in
+        results.logs.into_iter().zip(expected)
+    {
+        assert_eq!(log.id(), id);
+        assert_eq!(*log.source(), source);
+        let log = log.into_simple_log();
+        assert_eq!(
+            DateTime::parse_from_rfc3339(&log.timestamp).unwrap(),
+            DateTime::parse_from_rfc3339(timestamp).unwrap()
+        );
+        assert_eq!(log.level, level);
+        assert_eq!(log.location, location);
+        assert_eq!(log.content, content);
+    }
+
+    //Migrated rows are found through the columns and indexes added since the baseline
+    let agents = log_manager
+        .search(
+            &SearchFilter::default().source_prefix(&["Agent"]),
+            Sort::default(),
+            None,
+        )
+        .unwrap();
+    assert_eq!(agents.total_count, 2);
+    let full_text = log_manager
+        .search(
+            &SearchFilter::default().full_text("reply"),
+            Sort::default(),
+            None,
+        )
+        .unwrap();
+    assert_eq!(full_text.total_count, 1);
+
+    log_manager.stop();
+    remove_database(&database_url);
+}
@@ -0,0 +1,117 @@
+use std::{
+    env,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use log_manager::{
+    logs::{Level, SimpleLog},
+    manager::{Builder, Pagination},
+    search::{SearchFilter, Sort, SortDirection, SortField},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const CHILD_DATABASE_ENV: &str = "LOG_MANAGER_TEST_CHILD_DATABASE";
+const CHILD_INDEX_ENV: &str = "LOG_MANAGER_TEST_CHILD_INDEX";
+const PROCESSES: usize = 4;
+const LOGS_PER_PROCESS: usize = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum TestSource {
+    Process(usize),
+}
+
+///Runs inside each child process, small batches make the writers contend for the lock as often as possible
+fn write_logs(database_url: String, index: usize) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async move {
+        let log_manager = Builder::default()
+            .database_url(database_url)
+            .batch_size(10)
+            .build::<TestSource>()
+            .await
+            .unwrap();
+        for i in 0..LOGS_PER_PROCESS {
+            log_manager
+                .save_log(
+                    SimpleLog::generate_log(
+                        Level::Info,
+                        "tests/multi_process".into(),
+                        i.to_string(),
+                    ),
+                    TestSource::Process(index),
+                )
+                .unwrap();
+        }
+        log_manager.flush().await.unwrap();
+        log_manager.stop();
+    });
+}
+
+#[test]
+fn processes_share_one_database_file() {
+    if let (Ok(database_url), Ok(index)) = (env::var(CHILD_DATABASE_ENV), env::var(CHILD_INDEX_ENV))
+    {
+        write_logs(database_url, index.parse().unwrap());
+        return;
+    }
+
+    let database_path: PathBuf =
+        env::temp_dir().join(format!("log-manager-{}.sqlite", Uuid::new_v4()));
+    let database_url = database_path.to_string_lossy().to_string();
+    let children: Vec<_> = (0..PROCESSES)
+        .map(|index| {
+            Command::new(env::current_exe().unwrap())
+                .args([
+                    "--exact",
+                    "processes_share_one_database_file",
+                    "--nocapture",
+                ])
+                .env(CHILD_DATABASE_ENV, &database_url)
+                .env(CHILD_INDEX_ENV, index.to_string())
+                .stdout(Stdio::null())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let log_manager = Builder::default()
+            .database_url(database_url.to_owned())
+            .build::<TestSource>()
+            .await
+            .unwrap();
+        let results = log_manager
+            .search(
+                &SearchFilter::default(),
+                Sort::new(SortField::Id, SortDirection::Ascending),
+                Some(Pagination::After {
+                    cursor: None,
+                    limit: PROCESSES * LOGS_PER_PROCESS + 1,
+                }),
+            )
+            .unwrap();
+        assert_eq!(results.total_count as usize, PROCESSES * LOGS_PER_PROCESS);
+        assert_eq!(results.logs.len(), PROCESSES * LOGS_PER_PROCESS);
+        for index in 0..PROCESSES {
+            let results = log_manager
+                .search(
+                    &SearchFilter::default().source(TestSource::Process(index)),
+                    Sort::default(),
+                    None,
+                )
+                .unwrap();
+            assert_eq!(results.total_count as usize, LOGS_PER_PROCESS);
+        }
+        log_manager.stop();
+    });
+
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{database_url}{suffix}"));
+    }
+}
@@ -0,0 +1,146 @@
+use log_manager::{
+    error::Error,
+    logs::{Level, Log, SimpleLog},
+    manager::{Builder, LogManager, Pagination},
+    search::{SearchFilter, Sort, SortDirection, SortField},
+};
+use serde::{Deserialize, Serialize};
+
+const LOGS: usize = 23;
+const LIMIT: usize = 5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum TestSource {
+    Server,
+}
+
+fn ids(logs: Vec<Log<TestSource>>) -> Vec<i64> {
+    logs.into_iter().map(|log| i64::from(log.id())).collect()
+}
+
+///Pagination as a client would send it back, so cursors go through their string form
+fn round_trip(pagination: Pagination) -> Pagination {
+    serde_json::from_str(&serde_json::to_string(&pagination).unwrap()).unwrap()
+}
+
+///Follows next_cursor from the start (After) or the end (Before) until it runs out
+fn walk(log_manager: &LogManager<TestSource>, sort: Sort, after: bool) -> Vec<i64> {
+    let mut pagination = if after {
+        Pagination::After {
+            cursor: None,
+            limit: LIMIT,
+        }
+    } else {
+        Pagination::Before {
+            cursor: None,
+            limit: LIMIT,
+        }
+    };
+    let mut walked = Vec::new();
+    for _ in 0..=LOGS {
+        let results = log_manager
+            .search(&SearchFilter::default(), sort, Some(round_trip(pagination)))
+            .unwrap();
+        let page = ids(results.logs);
+        assert!(page.len() <= LIMIT);
+        if after {
+            walked.extend(page);
+        } else {
+            walked.splice(0..0, page);
+        }
+        let Some(cursor) = results.next_cursor else {
+            return walked;
+        };
+        pagination = if after {
+            Pagination::After {
+                cursor: Some(cursor),
+                limit: LIMIT,
+            }
+        } else {
+            Pagination::Before {
+                cursor: Some(cursor),
+                limit: LIMIT,
+            }
+        };
+    }
+    panic!("Pagination didn't terminate");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cursors_walk_every_sort_in_both_directions() {
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    //Ids ascend while timestamps descend in runs of three equal ones, so the sorts differ and ties need the id
+    let logs = (0..LOGS).map(|i| {
+        let mut log =
+            SimpleLog::generate_log(Level::Info, "tests/pagination".into(), i.to_string());
+        log.timestamp = format!("2026-10-17T12:{:02}:00Z", 59 - i / 3);
+        (log, TestSource::Server)
+    });
+    for result in log_manager.save_logs(logs).unwrap() {
+        result.unwrap();
+    }
+
+    for field in [SortField::Id, SortField::Timestamp] {
+        for direction in [SortDirection::Ascending, SortDirection::Descending] {
+            let sort = Sort::new(field, direction);
+            let all = ids(log_manager
+                .search(&SearchFilter::default(), sort, None)
+                .unwrap()
+                .logs);
+            assert_eq!(all.len(), LOGS);
+            assert_eq!(walk(&log_manager, sort, true), all, "After {sort:?}");
+            assert_eq!(walk(&log_manager, sort, false), all, "Before {sort:?}");
+        }
+    }
+
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pagination_which_overflows_is_rejected() {
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    for pagination in [
+        Pagination::Page {
+            page: usize::MAX,
+            page_size: 2,
+        },
+        //Fits in usize but not in SQLite's i64
+        Pagination::Page {
+            page: 3,
+            page_size: usize::MAX / 2,
+        },
+        Pagination::Page {
+            page: 1,
+            page_size: usize::MAX,
+        },
+        Pagination::After {
+            cursor: None,
+            limit: usize::MAX,
+        },
+    ] {
+        let result =
+            log_manager.search(&SearchFilter::default(), Sort::default(), Some(pagination));
+        assert!(matches!(result, Err(Error::InvalidPagination(_))));
+    }
+    //The first page never has an offset, however large the page
+    let results = log_manager
+        .search(
+            &SearchFilter::default(),
+            Sort::default(),
+            Some(Pagination::Page {
+                page: 1,
+                page_size: i64::MAX as usize,
+            }),
+        )
+        .unwrap();
+    assert_eq!(results.total_count, 0);
+    log_manager.stop();
+}
@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use log_manager::{
+    logs::{Level, SimpleLog},
+    manager::{Builder, LogManager},
+    retention::RetentionPolicy,
+    search::{SearchFilter, Sort, SortDirection, SortField},
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum TestSource {
+    Server,
+}
+
+fn aged_log(level: Level, age: TimeDelta, content: &str) -> (SimpleLog, TestSource) {
+    let mut log = SimpleLog::generate_log(level, "tests/retention".into(), content.into());
+    log.timestamp = (Utc::now() - age).to_rfc3339();
+    (log, TestSource::Server)
+}
+
+fn contents(log_manager: &LogManager<TestSource>) -> Vec<String> {
+    log_manager
+        .search(
+            &SearchFilter::default(),
+            Sort::new(SortField::Id, SortDirection::Ascending),
+            None,
+        )
+        .unwrap()
+        .logs
+        .into_iter()
+        .map(|log| log.into_simple_log().content)
+        .collect()
+}
+
+///Pruning runs in the background, waits for it to leave the expected logs behind
+async fn wait_for_contents(log_manager: &LogManager<TestSource>, expected: &[&str]) {
+    for _ in 0..100 {
+        if contents(log_manager) == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(contents(log_manager), expected);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn max_age_prunes_unless_the_level_overrides_it() {
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .retention(
+            RetentionPolicy::default()
+                .max_age(Duration::from_secs(60 * 60))
+                .level_max_age(Level::Warn, Duration::from_secs(2 * 24 * 60 * 60))
+                //Reaches back further than any timestamp, these are never removed
+                .level_max_age(Level::Error, Duration::MAX),
+        )
+        .retention_interval(Duration::from_millis(20))
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    let logs = [
+        aged_log(Level::Info, TimeDelta::hours(2), "old info"),
+        aged_log(Level::Info, TimeDelta::zero(), "new info"),
+        aged_log(Level::Error, TimeDelta::days(1000), "old error"),
+        aged_log(Level::Warn, TimeDelta::days(3), "old warn"),
+        aged_log(Level::Warn, TimeDelta::days(1), "new warn"),
+    ];
+    for result in log_manager.save_logs(logs).unwrap() {
+        result.unwrap();
+    }
+    wait_for_contents(&log_manager, &["new info", "old error", "new warn"]).await;
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn max_rows_keeps_the_newest_logs() {
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .retention(
+            RetentionPolicy::default()
+                .max_age(Duration::MAX)
+                .max_rows(3),
+        )
+        .retention_interval(Duration::from_millis(20))
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    let logs = (0..5).map(|i| aged_log(Level::Info, TimeDelta::minutes(i), &i.to_string()));
+    for result in log_manager.save_logs(logs).unwrap() {
+        result.unwrap();
+    }
+    wait_for_contents(&log_manager, &["0", "1", "2"]).await;
+    log_manager.stop();
+}
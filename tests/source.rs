@@ -0,0 +1,201 @@
+use std::{env, sync::Arc};
+
+use diesel::{connection::SimpleConnection, Connection, SqliteConnection};
+use log_manager::{
+    error::{BuilderError, Error},
+    logs::{Level, SimpleLog},
+    manager::{Builder, LogManager},
+    search::{SearchFilter, Sort, SortDirection, SortField},
+    source::Source,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+enum TestSource {
+    Server,
+    Agent(usize),
+    Worker { pool: String, id: usize },
+}
+
+fn sources() -> [(&'static str, TestSource); 5] {
+    [
+        ("server", TestSource::Server),
+        ("agent 1", TestSource::Agent(1)),
+        ("agent 5", TestSource::Agent(5)),
+        ("agent 15", TestSource::Agent(15)),
+        (
+            "worker",
+            TestSource::Worker {
+                pool: "io".into(),
+                id: 1,
+            },
+        ),
+    ]
+}
+
+fn temp_database() -> String {
+    env::temp_dir()
+        .join(format!("log-manager-{}.sqlite", Uuid::new_v4()))
+        .to_string_lossy()
+        .to_string()
+}
+
+fn remove_database(database_url: &str) {
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{database_url}{suffix}"));
+    }
+}
+
+fn save(log_manager: &LogManager<TestSource>) {
+    let logs = sources().map(|(content, source)| {
+        (
+            SimpleLog::generate_log(Level::Info, "tests/source".into(), content.into()),
+            source,
+        )
+    });
+    for result in log_manager.save_logs(logs).unwrap() {
+        result.unwrap();
+    }
+}
+
+fn matches(log_manager: &LogManager<TestSource>, prefix: &[&str]) -> Vec<String> {
+    log_manager
+        .search(
+            &SearchFilter::default().source_prefix(prefix),
+            Sort::new(SortField::Id, SortDirection::Ascending),
+            None,
+        )
+        .unwrap()
+        .logs
+        .into_iter()
+        .map(|log| log.into_simple_log().content)
+        .collect()
+}
+
+///Decomposes agents into a tier above their id
+fn tiered_path(source: &TestSource) -> Vec<String> {
+    match source {
+        TestSource::Agent(id) => {
+            let tier = if *id < 10 { "low" } else { "high" };
+            vec!["Agent".into(), tier.into(), id.to_string()]
+        }
+        source => source.source_path(),
+    }
+}
+
+#[test]
+fn paths_follow_the_serde_representation() {
+    assert_eq!(TestSource::Server.source_path(), ["Server"]);
+    assert_eq!(TestSource::Agent(5).source_path(), ["Agent", "5"]);
+    let worker = TestSource::Worker {
+        pool: "io".into(),
+        id: 1,
+    };
+    assert_eq!(worker.source_path(), ["Worker", "pool", "io", "id", "1"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn source_prefix_matches_whole_components() {
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    save(&log_manager);
+
+    assert_eq!(
+        matches(&log_manager, &["Agent"]),
+        ["agent 1", "agent 5", "agent 15"]
+    );
+    assert_eq!(matches(&log_manager, &["Agent", "5"]), ["agent 5"]);
+    //"1" isn't a prefix of the component "15"
+    assert_eq!(matches(&log_manager, &["Agent", "1"]), ["agent 1"]);
+    assert!(matches(&log_manager, &["Agen"]).is_empty());
+    assert_eq!(matches(&log_manager, &["Worker", "pool", "io"]), ["worker"]);
+    assert_eq!(matches(&log_manager, &[]).len(), sources().len());
+
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn source_path_can_be_overridden() {
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .source_path(tiered_path)
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    save(&log_manager);
+
+    assert_eq!(
+        matches(&log_manager, &["Agent", "low"]),
+        ["agent 1", "agent 5"]
+    );
+    assert_eq!(
+        matches(&log_manager, &["Agent", "high", "15"]),
+        ["agent 15"]
+    );
+    assert!(matches(&log_manager, &["Agent", "5"]).is_empty());
+    assert_eq!(matches(&log_manager, &["Server"]), ["server"]);
+
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn source_path_for_another_source_type_is_rejected() {
+    let result = Builder::default()
+        .database_url(":memory:".into())
+        .source_path(|source: &String| vec![source.to_owned()])
+        .build::<TestSource>()
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::Builder(BuilderError::SourceTypeMismatch(_)))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn missing_paths_are_filled_in_at_startup() {
+    let database_url = temp_database();
+    let build = |database_url: String| async move {
+        Builder::default()
+            .database_url(database_url)
+            .source_path(tiered_path)
+            .build::<TestSource>()
+            .await
+            .unwrap()
+    };
+    let log_manager: Arc<LogManager<TestSource>> = build(database_url.to_owned()).await;
+    save(&log_manager);
+    log_manager.stop();
+    drop(log_manager);
+
+    //As left by a version which didn't store paths, with a source which no longer deserializes
+    let mut connection = SqliteConnection::establish(&database_url).unwrap();
+    connection
+        .batch_execute(
+            r#"UPDATE log SET source_path = NULL;
+            INSERT INTO log (source, timestamp, level, location, content)
+                SELECT '"Removed"', timestamp, level, location, 'removed' FROM log LIMIT 1;"#,
+        )
+        .unwrap();
+    drop(connection);
+
+    let log_manager = build(database_url.to_owned()).await;
+    assert_eq!(
+        matches(&log_manager, &["Agent", "low"]),
+        ["agent 1", "agent 5"]
+    );
+    assert_eq!(matches(&log_manager, &["Worker"]), ["worker"]);
+    //Left without a path, so no prefix matches it
+    assert!(matches(&log_manager, &["Removed"]).is_empty());
+    let results = log_manager
+        .search(&SearchFilter::default(), Sort::default(), None)
+        .unwrap();
+    assert_eq!(results.total_count as usize, sources().len() + 1);
+    assert_eq!(results.failed.len(), 1);
+    log_manager.stop();
+    drop(log_manager);
+    remove_database(&database_url);
+}
@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use log_manager::{
+    error::Error,
+    logs::{Level, SimpleLog},
+    manager::Builder,
+    search::SearchFilter,
+    subscription::Subscription,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum TestSource {
+    Server,
+    Agent(usize),
+}
+
+fn log(level: Level, content: &str) -> SimpleLog {
+    SimpleLog::generate_log(level, "tests/subscription".into(), content.into())
+}
+
+async fn recv_content(subscription: &mut Subscription<TestSource>) -> String {
+    tokio::time::timeout(Duration::from_secs(5), subscription.recv())
+        .await
+        .unwrap()
+        .unwrap()
+        .into_simple_log()
+        .content
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn only_matching_logs_are_delivered() {
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    //Logs written before subscribing aren't delivered
+    log_manager
+        .save_logs([(log(Level::Error, "before"), TestSource::Agent(1))])
+        .unwrap();
+    let mut subscription = log_manager
+        .subscribe(
+            &SearchFilter::default()
+                .source(TestSource::Agent(1))
+                .min_level(Level::Warn),
+        )
+        .unwrap();
+    log_manager
+        .save_logs([
+            (log(Level::Error, "wrong source"), TestSource::Server),
+            (log(Level::Warn, "first"), TestSource::Agent(1)),
+            (log(Level::Info, "too low"), TestSource::Agent(1)),
+            (log(Level::Error, "wrong agent"), TestSource::Agent(2)),
+            (log(Level::Error, "second"), TestSource::Agent(1)),
+        ])
+        .unwrap();
+    //Queued logs are delivered once the writer commits them
+    log_manager
+        .save_log(log(Level::Warn, "third"), TestSource::Agent(1))
+        .unwrap();
+    assert_eq!(recv_content(&mut subscription).await, "first");
+    assert_eq!(recv_content(&mut subscription).await, "second");
+    assert_eq!(recv_content(&mut subscription).await, "third");
+    assert!(
+        tokio::time::timeout(Duration::from_millis(200), subscription.recv())
+            .await
+            .is_err()
+    );
+    //Full-text queries only run in SQLite
+    assert!(matches!(
+        log_manager.subscribe(&SearchFilter::default().full_text("first")),
+        Err(Error::UnsupportedFilter(_))
+    ));
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn slow_subscribers_are_told_how_many_logs_they_missed() {
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .subscription_capacity(2)
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    let mut subscription = log_manager.subscribe(&SearchFilter::default()).unwrap();
+    let logs =
+        ["1", "2", "3", "4", "5"].map(|content| (log(Level::Info, content), TestSource::Server));
+    log_manager.save_logs(logs).unwrap();
+    assert!(matches!(
+        subscription.recv().await,
+        Err(Error::SubscriptionLagged(3))
+    ));
+    //Carries on from the oldest log still buffered
+    assert_eq!(recv_content(&mut subscription).await, "4");
+    assert_eq!(recv_content(&mut subscription).await, "5");
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn subscriptions_close_when_the_manager_stops() {
+    let log_manager = Builder::default()
+        .database_url(":memory:".into())
+        .build::<TestSource>()
+        .await
+        .unwrap();
+    let mut subscription = log_manager.subscribe(&SearchFilter::default()).unwrap();
+    log_manager
+        .save_log(log(Level::Info, "last"), TestSource::Server)
+        .unwrap();
+    log_manager.stop();
+    //Logs queued before stopping are still delivered
+    assert_eq!(recv_content(&mut subscription).await, "last");
+    let closed = tokio::time::timeout(Duration::from_secs(5), subscription.recv())
+        .await
+        .unwrap();
+    assert!(matches!(closed, Err(Error::SubscriptionClosed)));
+    assert!(matches!(
+        log_manager.subscribe(&SearchFilter::default()),
+        Err(Error::WriterStopped)
+    ));
+}
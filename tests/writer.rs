@@ -0,0 +1,127 @@
+use std::{sync::Arc, time::Duration};
+
+use log_manager::{
+    error::Error,
+    logs::{Level, SimpleLog},
+    manager::{Builder, LogManager},
+    search::{SearchFilter, Sort, SortDirection, SortField},
+    writer::BackpressurePolicy,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum TestSource {
+    Server,
+}
+
+///Writes only when flushed, so the queue stays full between flushes
+async fn build(policy: BackpressurePolicy) -> Arc<LogManager<TestSource>> {
+    Builder::default()
+        .database_url(":memory:".into())
+        .queue_capacity(2)
+        .batch_size(1000)
+        .flush_interval(Duration::from_secs(60 * 60))
+        .backpressure_policy(policy)
+        .build::<TestSource>()
+        .await
+        .unwrap()
+}
+
+fn log(content: &str) -> SimpleLog {
+    SimpleLog::generate_log(Level::Info, "tests/writer".into(), content.into())
+}
+
+fn contents(log_manager: &LogManager<TestSource>) -> Vec<String> {
+    log_manager
+        .search(
+            &SearchFilter::default(),
+            Sort::new(SortField::Id, SortDirection::Ascending),
+            None,
+        )
+        .unwrap()
+        .logs
+        .into_iter()
+        .map(|log| log.into_simple_log().content)
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn drop_oldest_keeps_the_newest_logs() {
+    let log_manager = build(BackpressurePolicy::DropOldest).await;
+    for content in ["1", "2", "3", "4", "5"] {
+        log_manager
+            .save_log(log(content), TestSource::Server)
+            .unwrap();
+    }
+    assert_eq!(log_manager.dropped_logs(), 3);
+    assert!(matches!(
+        log_manager.flush().await,
+        Err(Error::LogsNotWritten)
+    ));
+    assert_eq!(contents(&log_manager), ["4", "5"]);
+    log_manager.save_log(log("6"), TestSource::Server).unwrap();
+    log_manager.flush().await.unwrap();
+    assert_eq!(contents(&log_manager), ["4", "5", "6"]);
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn drop_newest_keeps_the_oldest_logs() {
+    let log_manager = build(BackpressurePolicy::DropNewest).await;
+    for content in ["1", "2", "3", "4", "5"] {
+        log_manager
+            .save_log(log(content), TestSource::Server)
+            .unwrap();
+    }
+    assert_eq!(log_manager.dropped_logs(), 3);
+    assert!(matches!(
+        log_manager.flush().await,
+        Err(Error::LogsNotWritten)
+    ));
+    assert_eq!(contents(&log_manager), ["1", "2"]);
+    //Nothing was lost since the previous flush
+    log_manager.flush().await.unwrap();
+    log_manager.save_log(log("6"), TestSource::Server).unwrap();
+    log_manager.flush().await.unwrap();
+    assert_eq!(contents(&log_manager), ["1", "2", "6"]);
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn block_waits_for_the_writer_to_make_room() {
+    let log_manager = build(BackpressurePolicy::Block).await;
+    for content in ["1", "2"] {
+        log_manager
+            .save_log(log(content), TestSource::Server)
+            .unwrap();
+    }
+    let log_manager_ = log_manager.to_owned();
+    let blocked =
+        tokio::task::spawn_blocking(move || log_manager_.save_log(log("3"), TestSource::Server));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!blocked.is_finished());
+    log_manager.flush().await.unwrap();
+    blocked.await.unwrap().unwrap();
+    log_manager.flush().await.unwrap();
+    assert_eq!(log_manager.dropped_logs(), 0);
+    assert_eq!(contents(&log_manager), ["1", "2", "3"]);
+    log_manager.stop();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn logs_saved_after_stop_are_rejected() {
+    let log_manager = build(BackpressurePolicy::Block).await;
+    log_manager.save_log(log("1"), TestSource::Server).unwrap();
+    log_manager.stop();
+    assert!(matches!(
+        log_manager.save_log(log("2"), TestSource::Server),
+        Err(Error::WriterStopped)
+    ));
+    assert!(matches!(
+        log_manager.save_logs([(log("3"), TestSource::Server)]),
+        Err(Error::WriterStopped)
+    ));
+    //Logs queued before stop are still written
+    log_manager.flush().await.unwrap();
+    assert_eq!(contents(&log_manager), ["1"]);
+}